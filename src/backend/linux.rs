@@ -0,0 +1,538 @@
+use std::{
+    io,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use evdev::{uinput::VirtualDevice, AttributeSet, BusType, Device, EventType, InputEvent, KeyCode};
+
+use crate::{
+    engine::{KeyEvent, MacroStep, OutputEvent, Status, TICK_INTERVAL},
+    global::Global,
+    keys::KeyState,
+    scope::{NoWindow, WindowProvider},
+    vk_table::VirtualKey,
+};
+
+const VIRTUAL_DEVICE_NAME: &str = "ein-key virtual keyboard";
+
+pub struct Hook {
+    running: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<io::Result<()>>>,
+}
+
+pub fn install_hook() -> Hook {
+    let running = Arc::new(AtomicBool::new(true));
+    let devices = evdev::enumerate()
+        .filter(|(_, device)| is_keyboard(device))
+        .collect::<Vec<_>>();
+
+    let threads = devices
+        .into_iter()
+        .map(|(path, device)| {
+            let running = running.clone();
+            thread::spawn(move || {
+                let result = run_device(device, &running);
+                if let Err(err) = &result {
+                    println!("Keyboard {:?} stopped: {}", path, err);
+                }
+                result
+            })
+        })
+        .collect();
+    Hook { running, threads }
+}
+
+pub fn delete_hook(hook: Hook) {
+    hook.running.store(false, Ordering::Relaxed);
+}
+
+/// Block the current thread while the hook is active, fails if no keyboard was remapped.
+pub fn wait(hook: &mut Hook) -> io::Result<()> {
+    if hook.threads.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "No keyboard found, check the read access to /dev/input/event*",
+        ));
+    }
+    let mut result = Err(io::Error::other("Every keyboard stopped"));
+    for thread in hook.threads.drain(..) {
+        if let Ok(Ok(())) = thread.join() {
+            result = Ok(());
+        }
+    }
+    result
+}
+
+/// The foreground window is not known without a display server connection, scoped mappings never match.
+pub fn window_provider() -> Box<dyn WindowProvider> {
+    Box::new(NoWindow)
+}
+
+/// Source of raw input events, implemented by grabbed evdev devices and by fakes.
+pub trait EventSource {
+    fn fetch(&mut self) -> io::Result<Vec<InputEvent>>;
+}
+
+/// Target for the remapped input events, implemented by uinput devices and by fakes.
+pub trait EventSink {
+    fn emit(&mut self, events: &[InputEvent]) -> io::Result<()>;
+}
+
+impl EventSource for Device {
+    fn fetch(&mut self) -> io::Result<Vec<InputEvent>> {
+        Ok(self.fetch_events()?.collect())
+    }
+}
+
+impl EventSink for VirtualDevice {
+    fn emit(&mut self, events: &[InputEvent]) -> io::Result<()> {
+        VirtualDevice::emit(self, events)
+    }
+}
+
+fn is_keyboard(device: &Device) -> bool {
+    if device.name() == Some(VIRTUAL_DEVICE_NAME) {
+        return false;
+    }
+    device
+        .supported_keys()
+        .is_some_and(|keys| keys.contains(KeyCode::KEY_A) && keys.contains(KeyCode::KEY_ENTER))
+}
+
+fn run_device(mut device: Device, running: &AtomicBool) -> io::Result<()> {
+    // keys held while grabbing would never be released for the rest of the system
+    while device.get_key_state()?.iter().next().is_some() {
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    let mut keys = AttributeSet::<KeyCode>::new();
+    for (code, _) in KEY_TABLE {
+        keys.insert(*code);
+    }
+    if let Some(supported) = device.supported_keys() {
+        for code in supported.iter() {
+            keys.insert(code);
+        }
+    }
+    let mut virtual_device = VirtualDevice::builder()?
+        .name(VIRTUAL_DEVICE_NAME)
+        .with_keys(&keys)?
+        .build()?;
+
+    // events of other uinput devices count as injected, the own device is never grabbed
+    let injected = device.input_id().bus_type() == BusType::BUS_VIRTUAL;
+    device.grab()?;
+    let result = forward(
+        &mut device,
+        &mut virtual_device,
+        running,
+        |event| Global::handle_event(KeyEvent { injected, ..event }),
+        Global::tick,
+    );
+    device.ungrab()?;
+    result
+}
+
+/// Events of one batch, the waits of macros are between them.
+enum Emit {
+    Events(Vec<InputEvent>),
+    Wait(u32),
+}
+
+/// Pass every event from the source through `handle` and write the results to the sink,
+/// `tick` is called with the current time between the events.
+pub fn forward(
+    source: &mut impl EventSource,
+    sink: &mut (impl EventSink + Send),
+    running: &AtomicBool,
+    mut handle: impl FnMut(KeyEvent) -> Status,
+    tick: impl Fn(u32) -> Vec<MacroStep> + Sync,
+) -> io::Result<()> {
+    let (sender, receiver) = mpsc::channel();
+    let reading = AtomicBool::new(true);
+    thread::scope(|scope| {
+        // the waits of macros must not block reading the source
+        let player = scope.spawn(move || play(sink, receiver));
+        let tick_sender = sender.clone();
+        let (reading, tick) = (&reading, &tick);
+        scope.spawn(move || ticks(reading, tick_sender, tick));
+        let result = read(source, sender, running, &mut handle);
+        reading.store(false, Ordering::Relaxed);
+        player.join().unwrap().and(result)
+    })
+}
+
+/// Timeouts are resolved even if no further event arrives.
+fn ticks(reading: &AtomicBool, sender: Sender<Vec<Emit>>, tick: impl Fn(u32) -> Vec<MacroStep>) {
+    while reading.load(Ordering::Relaxed) {
+        thread::sleep(Duration::from_millis(TICK_INTERVAL.into()));
+        let mut emits = Vec::new();
+        push_steps(&mut emits, tick(milliseconds(SystemTime::now())));
+        if emits.is_empty() {
+            continue;
+        }
+        if sender.send(emits).is_err() {
+            break;
+        }
+    }
+}
+
+fn read(
+    source: &mut impl EventSource,
+    sender: Sender<Vec<Emit>>,
+    running: &AtomicBool,
+    handle: &mut impl FnMut(KeyEvent) -> Status,
+) -> io::Result<()> {
+    while running.load(Ordering::Relaxed) {
+        let events = source.fetch()?;
+        let emits = process(events, handle);
+        if emits.is_empty() {
+            continue;
+        }
+        // the player stopped after an error
+        if sender.send(emits).is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Emit in order, later events wait for the macros before them.
+fn play(sink: &mut impl EventSink, receiver: Receiver<Vec<Emit>>) -> io::Result<()> {
+    for emits in receiver {
+        for emit in emits {
+            match emit {
+                Emit::Events(events) => sink.emit(&events)?,
+                Emit::Wait(milliseconds) => {
+                    thread::sleep(Duration::from_millis(milliseconds.into()))
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn process(events: Vec<InputEvent>, handle: &mut impl FnMut(KeyEvent) -> Status) -> Vec<Emit> {
+    let mut emits = Vec::new();
+    for event in events {
+        if event.event_type() != EventType::KEY {
+            continue;
+        }
+        let Some(key) = virtual_key(KeyCode(event.code())) else {
+            push_events(&mut emits, [event]);
+            continue;
+        };
+
+        let key_event = KeyEvent {
+            key,
+            state: match event.value() {
+                0 => KeyState::Released,
+                _ => KeyState::Pressed,
+            },
+            injected: false,
+            time: milliseconds(event.timestamp()),
+        };
+        match handle(key_event) {
+            Status::Allow => push_events(&mut emits, [event]),
+            Status::Intercept => {}
+            Status::Replace(replace) => {
+                push_events(&mut emits, replace.iter().filter_map(create_event))
+            }
+            Status::Macro(steps) => push_steps(&mut emits, steps),
+        }
+    }
+    emits
+}
+
+fn push_steps(emits: &mut Vec<Emit>, steps: Vec<MacroStep>) {
+    for step in steps {
+        match step {
+            MacroStep::Send(outputs) => push_events(emits, outputs.iter().filter_map(create_event)),
+            MacroStep::Text(text) => push_events(emits, create_text(&text)),
+            MacroStep::Wait(milliseconds) => emits.push(Emit::Wait(milliseconds)),
+        }
+    }
+}
+
+/// Same clock for the event timestamps and the ticks.
+fn milliseconds(time: SystemTime) -> u32 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_millis() as u32)
+}
+
+fn push_events(emits: &mut Vec<Emit>, events: impl IntoIterator<Item = InputEvent>) {
+    match emits.last_mut() {
+        Some(Emit::Events(last)) => last.extend(events),
+        _ => emits.push(Emit::Events(events.into_iter().collect())),
+    }
+}
+
+fn create_event(output: &OutputEvent) -> Option<InputEvent> {
+    let code = key_code(output.key)?;
+    let value = match output.state {
+        KeyState::Pressed => 1,
+        KeyState::Released => 0,
+    };
+    Some(InputEvent::new(EventType::KEY.0, code.0, value))
+}
+
+/// Unicode input of GTK and IBus, Control+Shift+U followed by the hex code and Space.
+fn create_text(text: &str) -> Vec<InputEvent> {
+    let mut events = Vec::new();
+    let mut push =
+        |code: KeyCode, value| events.push(InputEvent::new(EventType::KEY.0, code.0, value));
+    for character in text.chars() {
+        push(KeyCode::KEY_LEFTCTRL, 1);
+        push(KeyCode::KEY_LEFTSHIFT, 1);
+        push(KeyCode::KEY_U, 1);
+        push(KeyCode::KEY_U, 0);
+        push(KeyCode::KEY_LEFTSHIFT, 0);
+        push(KeyCode::KEY_LEFTCTRL, 0);
+        // virtual keys of digits and letters match their uppercase ASCII code
+        for digit in format!("{:X}", u32::from(character)).bytes() {
+            let Some(code) = key_code(VirtualKey(digit.into())) else {
+                continue;
+            };
+            push(code, 1);
+            push(code, 0);
+        }
+        push(KeyCode::KEY_SPACE, 1);
+        push(KeyCode::KEY_SPACE, 0);
+    }
+    events
+}
+
+fn virtual_key(code: KeyCode) -> Option<VirtualKey> {
+    KEY_TABLE
+        .iter()
+        .find_map(|(other, key)| (*other == code).then_some(*key))
+}
+
+fn key_code(key: VirtualKey) -> Option<KeyCode> {
+    KEY_TABLE
+        .iter()
+        .find_map(|(code, other)| (*other == key).then_some(*code))
+}
+
+/// Linux key codes and the matching virtual keys, the first entry wins in both directions.
+const KEY_TABLE: &[(KeyCode, VirtualKey)] = &[
+    (KeyCode::KEY_BACKSPACE, VirtualKey(0x08)),
+    (KeyCode::KEY_TAB, VirtualKey(0x09)),
+    (KeyCode::KEY_ENTER, VirtualKey(0x0D)),
+    (KeyCode::KEY_KPENTER, VirtualKey(0x0D)),
+    (KeyCode::KEY_PAUSE, VirtualKey(0x13)),
+    (KeyCode::KEY_CAPSLOCK, VirtualKey(0x14)),
+    (KeyCode::KEY_ESC, VirtualKey(0x1B)),
+    (KeyCode::KEY_SPACE, VirtualKey(0x20)),
+    (KeyCode::KEY_PAGEUP, VirtualKey(0x21)),
+    (KeyCode::KEY_PAGEDOWN, VirtualKey(0x22)),
+    (KeyCode::KEY_END, VirtualKey(0x23)),
+    (KeyCode::KEY_HOME, VirtualKey(0x24)),
+    (KeyCode::KEY_LEFT, VirtualKey(0x25)),
+    (KeyCode::KEY_UP, VirtualKey(0x26)),
+    (KeyCode::KEY_RIGHT, VirtualKey(0x27)),
+    (KeyCode::KEY_DOWN, VirtualKey(0x28)),
+    (KeyCode::KEY_SYSRQ, VirtualKey(0x2C)),
+    (KeyCode::KEY_INSERT, VirtualKey(0x2D)),
+    (KeyCode::KEY_DELETE, VirtualKey(0x2E)),
+    (KeyCode::KEY_HELP, VirtualKey(0x2F)),
+    (KeyCode::KEY_0, VirtualKey(0x30)),
+    (KeyCode::KEY_1, VirtualKey(0x31)),
+    (KeyCode::KEY_2, VirtualKey(0x32)),
+    (KeyCode::KEY_3, VirtualKey(0x33)),
+    (KeyCode::KEY_4, VirtualKey(0x34)),
+    (KeyCode::KEY_5, VirtualKey(0x35)),
+    (KeyCode::KEY_6, VirtualKey(0x36)),
+    (KeyCode::KEY_7, VirtualKey(0x37)),
+    (KeyCode::KEY_8, VirtualKey(0x38)),
+    (KeyCode::KEY_9, VirtualKey(0x39)),
+    (KeyCode::KEY_A, VirtualKey(0x41)),
+    (KeyCode::KEY_B, VirtualKey(0x42)),
+    (KeyCode::KEY_C, VirtualKey(0x43)),
+    (KeyCode::KEY_D, VirtualKey(0x44)),
+    (KeyCode::KEY_E, VirtualKey(0x45)),
+    (KeyCode::KEY_F, VirtualKey(0x46)),
+    (KeyCode::KEY_G, VirtualKey(0x47)),
+    (KeyCode::KEY_H, VirtualKey(0x48)),
+    (KeyCode::KEY_I, VirtualKey(0x49)),
+    (KeyCode::KEY_J, VirtualKey(0x4A)),
+    (KeyCode::KEY_K, VirtualKey(0x4B)),
+    (KeyCode::KEY_L, VirtualKey(0x4C)),
+    (KeyCode::KEY_M, VirtualKey(0x4D)),
+    (KeyCode::KEY_N, VirtualKey(0x4E)),
+    (KeyCode::KEY_O, VirtualKey(0x4F)),
+    (KeyCode::KEY_P, VirtualKey(0x50)),
+    (KeyCode::KEY_Q, VirtualKey(0x51)),
+    (KeyCode::KEY_R, VirtualKey(0x52)),
+    (KeyCode::KEY_S, VirtualKey(0x53)),
+    (KeyCode::KEY_T, VirtualKey(0x54)),
+    (KeyCode::KEY_U, VirtualKey(0x55)),
+    (KeyCode::KEY_V, VirtualKey(0x56)),
+    (KeyCode::KEY_W, VirtualKey(0x57)),
+    (KeyCode::KEY_X, VirtualKey(0x58)),
+    (KeyCode::KEY_Y, VirtualKey(0x59)),
+    (KeyCode::KEY_Z, VirtualKey(0x5A)),
+    (KeyCode::KEY_LEFTMETA, VirtualKey(0x5B)),
+    (KeyCode::KEY_RIGHTMETA, VirtualKey(0x5C)),
+    (KeyCode::KEY_COMPOSE, VirtualKey(0x5D)),
+    (KeyCode::KEY_SLEEP, VirtualKey(0x5F)),
+    (KeyCode::KEY_KP0, VirtualKey(0x60)),
+    (KeyCode::KEY_KP1, VirtualKey(0x61)),
+    (KeyCode::KEY_KP2, VirtualKey(0x62)),
+    (KeyCode::KEY_KP3, VirtualKey(0x63)),
+    (KeyCode::KEY_KP4, VirtualKey(0x64)),
+    (KeyCode::KEY_KP5, VirtualKey(0x65)),
+    (KeyCode::KEY_KP6, VirtualKey(0x66)),
+    (KeyCode::KEY_KP7, VirtualKey(0x67)),
+    (KeyCode::KEY_KP8, VirtualKey(0x68)),
+    (KeyCode::KEY_KP9, VirtualKey(0x69)),
+    (KeyCode::KEY_KPASTERISK, VirtualKey(0x6A)),
+    (KeyCode::KEY_KPPLUS, VirtualKey(0x6B)),
+    (KeyCode::KEY_KPCOMMA, VirtualKey(0x6C)),
+    (KeyCode::KEY_KPMINUS, VirtualKey(0x6D)),
+    (KeyCode::KEY_KPDOT, VirtualKey(0x6E)),
+    (KeyCode::KEY_KPSLASH, VirtualKey(0x6F)),
+    (KeyCode::KEY_F1, VirtualKey(0x70)),
+    (KeyCode::KEY_F2, VirtualKey(0x71)),
+    (KeyCode::KEY_F3, VirtualKey(0x72)),
+    (KeyCode::KEY_F4, VirtualKey(0x73)),
+    (KeyCode::KEY_F5, VirtualKey(0x74)),
+    (KeyCode::KEY_F6, VirtualKey(0x75)),
+    (KeyCode::KEY_F7, VirtualKey(0x76)),
+    (KeyCode::KEY_F8, VirtualKey(0x77)),
+    (KeyCode::KEY_F9, VirtualKey(0x78)),
+    (KeyCode::KEY_F10, VirtualKey(0x79)),
+    (KeyCode::KEY_F11, VirtualKey(0x7A)),
+    (KeyCode::KEY_F12, VirtualKey(0x7B)),
+    (KeyCode::KEY_F13, VirtualKey(0x7C)),
+    (KeyCode::KEY_F14, VirtualKey(0x7D)),
+    (KeyCode::KEY_F15, VirtualKey(0x7E)),
+    (KeyCode::KEY_F16, VirtualKey(0x7F)),
+    (KeyCode::KEY_F17, VirtualKey(0x80)),
+    (KeyCode::KEY_F18, VirtualKey(0x81)),
+    (KeyCode::KEY_F19, VirtualKey(0x82)),
+    (KeyCode::KEY_F20, VirtualKey(0x83)),
+    (KeyCode::KEY_F21, VirtualKey(0x84)),
+    (KeyCode::KEY_F22, VirtualKey(0x85)),
+    (KeyCode::KEY_F23, VirtualKey(0x86)),
+    (KeyCode::KEY_F24, VirtualKey(0x87)),
+    (KeyCode::KEY_NUMLOCK, VirtualKey(0x90)),
+    (KeyCode::KEY_SCROLLLOCK, VirtualKey(0x91)),
+    (KeyCode::KEY_LEFTSHIFT, VirtualKey(0xA0)),
+    (KeyCode::KEY_RIGHTSHIFT, VirtualKey(0xA1)),
+    (KeyCode::KEY_LEFTCTRL, VirtualKey(0xA2)),
+    (KeyCode::KEY_RIGHTCTRL, VirtualKey(0xA3)),
+    (KeyCode::KEY_LEFTALT, VirtualKey(0xA4)),
+    (KeyCode::KEY_RIGHTALT, VirtualKey(0xA5)),
+    (KeyCode::KEY_BACK, VirtualKey(0xA6)),
+    (KeyCode::KEY_FORWARD, VirtualKey(0xA7)),
+    (KeyCode::KEY_REFRESH, VirtualKey(0xA8)),
+    (KeyCode::KEY_STOP, VirtualKey(0xA9)),
+    (KeyCode::KEY_SEARCH, VirtualKey(0xAA)),
+    (KeyCode::KEY_BOOKMARKS, VirtualKey(0xAB)),
+    (KeyCode::KEY_HOMEPAGE, VirtualKey(0xAC)),
+    (KeyCode::KEY_MUTE, VirtualKey(0xAD)),
+    (KeyCode::KEY_VOLUMEDOWN, VirtualKey(0xAE)),
+    (KeyCode::KEY_VOLUMEUP, VirtualKey(0xAF)),
+    (KeyCode::KEY_NEXTSONG, VirtualKey(0xB0)),
+    (KeyCode::KEY_PREVIOUSSONG, VirtualKey(0xB1)),
+    (KeyCode::KEY_STOPCD, VirtualKey(0xB2)),
+    (KeyCode::KEY_PLAYPAUSE, VirtualKey(0xB3)),
+    (KeyCode::KEY_MAIL, VirtualKey(0xB4)),
+    (KeyCode::KEY_MEDIA, VirtualKey(0xB5)),
+    (KeyCode::KEY_PROG1, VirtualKey(0xB6)),
+    (KeyCode::KEY_CALC, VirtualKey(0xB7)),
+    (KeyCode::KEY_SEMICOLON, VirtualKey(0xBA)),
+    (KeyCode::KEY_EQUAL, VirtualKey(0xBB)),
+    (KeyCode::KEY_COMMA, VirtualKey(0xBC)),
+    (KeyCode::KEY_MINUS, VirtualKey(0xBD)),
+    (KeyCode::KEY_DOT, VirtualKey(0xBE)),
+    (KeyCode::KEY_SLASH, VirtualKey(0xBF)),
+    (KeyCode::KEY_GRAVE, VirtualKey(0xC0)),
+    (KeyCode::KEY_LEFTBRACE, VirtualKey(0xDB)),
+    (KeyCode::KEY_BACKSLASH, VirtualKey(0xDC)),
+    (KeyCode::KEY_RIGHTBRACE, VirtualKey(0xDD)),
+    (KeyCode::KEY_APOSTROPHE, VirtualKey(0xDE)),
+    (KeyCode::KEY_102ND, VirtualKey(0xE2)),
+    (KeyCode::KEY_PLAY, VirtualKey(0xFA)),
+    (KeyCode::KEY_ZOOM, VirtualKey(0xFB)),
+    (KeyCode::KEY_LEFTSHIFT, VirtualKey(0x10)),
+    (KeyCode::KEY_LEFTCTRL, VirtualKey(0x11)),
+    (KeyCode::KEY_LEFTALT, VirtualKey(0x12)),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Source {
+        batches: Vec<Vec<InputEvent>>,
+        running: Arc<AtomicBool>,
+    }
+
+    impl EventSource for Source {
+        fn fetch(&mut self) -> io::Result<Vec<InputEvent>> {
+            if self.batches.is_empty() {
+                self.running.store(false, Ordering::Relaxed);
+                return Ok(Vec::new());
+            }
+            Ok(self.batches.remove(0))
+        }
+    }
+
+    #[derive(Default)]
+    struct Sink(Vec<(KeyCode, i32)>);
+
+    impl EventSink for Sink {
+        fn emit(&mut self, events: &[InputEvent]) -> io::Result<()> {
+            self.0.extend(
+                events
+                    .iter()
+                    .map(|event| (KeyCode(event.code()), event.value())),
+            );
+            Ok(())
+        }
+    }
+
+    fn key(code: KeyCode, value: i32) -> InputEvent {
+        InputEvent::new(EventType::KEY.0, code.0, value)
+    }
+
+    #[test]
+    fn forward_keeps_the_order() {
+        let running = Arc::new(AtomicBool::new(true));
+        let mut source = Source {
+            batches: vec![
+                vec![key(KeyCode::KEY_A, 1), key(KeyCode::KEY_A, 0)],
+                vec![key(KeyCode::KEY_M, 1), key(KeyCode::KEY_B, 1)],
+            ],
+            running: running.clone(),
+        };
+        let mut sink = Sink::default();
+        let output = |key, state| OutputEvent { key, state };
+        let handle = |event: KeyEvent| match event.key.name() {
+            "A" => Status::Replace(vec![output(VirtualKey(0x43), event.state)]),
+            "M" => Status::Macro(vec![
+                MacroStep::Wait(20),
+                MacroStep::Send(vec![output(VirtualKey(0x44), KeyState::Pressed)]),
+            ]),
+            _ => Status::Allow,
+        };
+        forward(&mut source, &mut sink, &running, handle, |_| Vec::new()).unwrap();
+        assert_eq!(
+            sink.0,
+            [
+                (KeyCode::KEY_C, 1),
+                (KeyCode::KEY_C, 0),
+                (KeyCode::KEY_D, 1),
+                (KeyCode::KEY_B, 1),
+            ]
+        );
+    }
+}
@@ -0,0 +1,248 @@
+use std::{
+    io,
+    ops::Not,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, Sender},
+        LazyLock, Once,
+    },
+    thread,
+    time::Duration,
+};
+
+use windows::{
+    core::PWSTR,
+    Win32::{
+        Foundation::*,
+        System::{SystemInformation::GetTickCount, Threading::*},
+        UI::{Input::KeyboardAndMouse::*, WindowsAndMessaging::*},
+    },
+};
+
+use crate::{
+    engine::{KeyEvent, MacroStep, OutputEvent, Status, TICK_INTERVAL},
+    global::Global,
+    keys::KeyState,
+    scope::{WindowInfo, WindowProvider},
+    vk_table::VirtualKey,
+};
+
+pub type Hook = HHOOK;
+
+/// `dwExtraInfo` of the sent events, they are passed on without remapping.
+const SIGNATURE: usize = 0x6569_6B79;
+
+/// Plays the macros one after another, the hook must return without waiting.
+static PLAYER: LazyLock<Sender<Vec<MacroStep>>> = LazyLock::new(|| {
+    let (sender, receiver) = mpsc::channel::<Vec<MacroStep>>();
+    thread::spawn(move || {
+        for steps in receiver {
+            for step in steps {
+                match step {
+                    MacroStep::Send(outputs) => send(&outputs),
+                    MacroStep::Text(text) => send_text(&text),
+                    MacroStep::Wait(milliseconds) => {
+                        thread::sleep(Duration::from_millis(milliseconds.into()))
+                    }
+                }
+            }
+            QUEUED.fetch_sub(1, Ordering::SeqCst);
+        }
+    });
+    sender
+});
+
+/// Batches sent to the player and not finished yet, later events wait for them.
+static QUEUED: AtomicUsize = AtomicUsize::new(0);
+
+fn play(steps: Vec<MacroStep>) {
+    QUEUED.fetch_add(1, Ordering::SeqCst);
+    let _ = PLAYER.send(steps);
+}
+
+static TICKER: Once = Once::new();
+
+/// Timeouts are resolved even if no further key is pressed, with the clock of the hook events.
+fn tick() {
+    loop {
+        thread::sleep(Duration::from_millis(TICK_INTERVAL.into()));
+        let steps = Global::tick(unsafe { GetTickCount() });
+        if steps.is_empty().not() {
+            play(steps);
+        }
+    }
+}
+
+pub fn install_hook() -> Hook {
+    TICKER.call_once(|| {
+        thread::spawn(tick);
+    });
+    unsafe { SetWindowsHookExW(WH_KEYBOARD_LL, Some(low_level_keyboard_proc), None, 0).unwrap() }
+}
+
+pub fn delete_hook(hook: Hook) {
+    unsafe { UnhookWindowsHookEx(hook) }.unwrap();
+}
+
+/// Block the current thread while the hook is active, the hook is only called during the message loop.
+pub fn wait(_hook: &mut Hook) -> io::Result<()> {
+    let mut message = MSG::default();
+    loop {
+        match unsafe { GetMessageW(&mut message, None, 0, 0) }.0 {
+            -1 => return Err(io::Error::last_os_error()),
+            0 => return Ok(()),
+            _ => {}
+        }
+    }
+}
+
+pub fn window_provider() -> Box<dyn WindowProvider> {
+    Box::new(ForegroundWindow)
+}
+
+#[derive(Debug)]
+struct ForegroundWindow;
+
+impl WindowProvider for ForegroundWindow {
+    fn foreground(&self) -> Option<WindowInfo> {
+        let window = unsafe { GetForegroundWindow() };
+        if window.is_invalid() {
+            return None;
+        }
+
+        let mut title = [0; 512];
+        let length = unsafe { GetWindowTextW(window, &mut title) };
+        let title = String::from_utf16_lossy(&title[..length.max(0) as usize]);
+
+        let mut process_id = 0;
+        unsafe { GetWindowThreadProcessId(window, Some(&mut process_id)) };
+        let process = process_name(process_id).unwrap_or_default();
+
+        Some(WindowInfo { process, title })
+    }
+}
+
+/// File name of the executable of the process.
+fn process_name(process_id: u32) -> Option<String> {
+    let process =
+        unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, process_id) }.ok()?;
+    let mut path = [0; 1024];
+    let mut length = path.len() as u32;
+    let result = unsafe {
+        QueryFullProcessImageNameW(
+            process,
+            PROCESS_NAME_WIN32,
+            PWSTR(path.as_mut_ptr()),
+            &mut length,
+        )
+    };
+    let _ = unsafe { CloseHandle(process) };
+    result.ok()?;
+
+    let path = String::from_utf16_lossy(&path[..length as usize]);
+    path.rsplit('\\').next().map(String::from)
+}
+
+extern "system" fn low_level_keyboard_proc(
+    n_code: i32,
+    w_param: WPARAM,
+    l_param: LPARAM,
+) -> LRESULT {
+    if n_code as u32 == HC_ACTION
+        && (w_param == WPARAM(WM_KEYDOWN as usize)
+            || w_param == WPARAM(WM_SYSKEYDOWN as usize)
+            || w_param == WPARAM(WM_KEYUP as usize)
+            || w_param == WPARAM(WM_SYSKEYUP as usize))
+    {
+        let kb_struct = unsafe { &*(l_param.0 as *const KBDLLHOOKSTRUCT) };
+        if kb_struct.dwExtraInfo == SIGNATURE {
+            return unsafe { CallNextHookEx(None, n_code, w_param, l_param) };
+        }
+        let event = KeyEvent {
+            key: VirtualKey(kb_struct.vkCode as u16),
+            state: match kb_struct.flags.contains(LLKHF_UP) {
+                false => KeyState::Pressed,
+                true => KeyState::Released,
+            },
+            injected: kb_struct.flags.contains(LLKHF_INJECTED),
+            time: kb_struct.time,
+        };
+
+        // keep the order of the outputs while a macro is playing
+        let busy = QUEUED.load(Ordering::SeqCst) > 0;
+        match Global::handle_event(event) {
+            Status::Intercept => return LRESULT(1),
+            Status::Replace(outputs) if busy => {
+                play(vec![MacroStep::Send(outputs)]);
+                return LRESULT(1);
+            }
+            Status::Replace(outputs) => {
+                send(&outputs);
+                return LRESULT(1);
+            }
+            Status::Macro(steps) => {
+                play(steps);
+                return LRESULT(1);
+            }
+            Status::Allow if busy => {
+                let output = OutputEvent {
+                    key: event.key,
+                    state: event.state,
+                };
+                play(vec![MacroStep::Send(vec![output])]);
+                return LRESULT(1);
+            }
+            Status::Allow => {}
+        }
+    }
+
+    unsafe { CallNextHookEx(None, n_code, w_param, l_param) }
+}
+
+fn send(outputs: &[OutputEvent]) {
+    let inputs = create_inputs(outputs);
+    unsafe { SendInput(&inputs, size_of::<INPUT>() as i32) };
+}
+
+/// Each UTF-16 unit is sent as a key press and release of a packet key.
+fn send_text(text: &str) {
+    let inputs = text
+        .encode_utf16()
+        .flat_map(|unit| {
+            [KEYBD_EVENT_FLAGS(0), KEYEVENTF_KEYUP].map(|flags| INPUT {
+                r#type: INPUT_KEYBOARD,
+                Anonymous: INPUT_0 {
+                    ki: KEYBDINPUT {
+                        wVk: VIRTUAL_KEY(0),
+                        wScan: unit,
+                        dwFlags: KEYEVENTF_UNICODE | flags,
+                        time: 0,
+                        dwExtraInfo: SIGNATURE,
+                    },
+                },
+            })
+        })
+        .collect::<Vec<_>>();
+    unsafe { SendInput(&inputs, size_of::<INPUT>() as i32) };
+}
+
+fn create_inputs(outputs: &[OutputEvent]) -> Vec<INPUT> {
+    outputs
+        .iter()
+        .map(|output| INPUT {
+            r#type: INPUT_KEYBOARD,
+            Anonymous: INPUT_0 {
+                ki: KEYBDINPUT {
+                    wVk: VIRTUAL_KEY(output.key.0),
+                    wScan: 0,
+                    dwFlags: match output.state {
+                        KeyState::Pressed => KEYBD_EVENT_FLAGS(0),
+                        KeyState::Released => KEYEVENTF_KEYUP,
+                    },
+                    time: 0,
+                    dwExtraInfo: SIGNATURE,
+                },
+            },
+        })
+        .collect()
+}
@@ -0,0 +1,120 @@
+use std::{ops::Not, path::PathBuf, process::ExitCode};
+
+use crate::{
+    backend, config,
+    global::Global,
+    keys::Mapping,
+    lint::{describe, lint},
+};
+
+pub const USAGE: &str = "\
+Usage:
+    ein-key                               open the editor
+    ein-key run --config <FILE> [--check] apply the mappings without a window
+    ein-key lint --config <FILE>          report problems of the mappings";
+
+pub enum Command {
+    Gui,
+    Run { config: PathBuf, check: bool },
+    Lint { config: PathBuf },
+}
+
+impl Command {
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let lint = match args.next().as_deref() {
+            None => return Ok(Self::Gui),
+            Some("run") => false,
+            Some("lint") => true,
+            Some(other) => return Err(format!("Unknown command '{}'", other)),
+        };
+
+        let mut config = None;
+        let mut check = false;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--config" => match args.next() {
+                    Some(path) => config = Some(PathBuf::from(path)),
+                    None => return Err("Missing value for '--config'".into()),
+                },
+                "--check" if lint.not() => check = true,
+                other => return Err(format!("Unknown argument '{}'", other)),
+            }
+        }
+
+        let Some(config) = config else {
+            return Err("Missing '--config <FILE>'".into());
+        };
+        if lint {
+            return Ok(Self::Lint { config });
+        }
+        Ok(Self::Run { config, check })
+    }
+}
+
+pub fn run(path: PathBuf, check: bool) -> ExitCode {
+    let profiles = match config::load(&path) {
+        Ok(profiles) => profiles,
+        Err(err) => {
+            eprintln!("{}", err);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if check {
+        for profile in profiles.list() {
+            println!("{}: {} mappings", profile.name, profile.mappings.len());
+        }
+        return ExitCode::SUCCESS;
+    }
+
+    Global::load(profiles, path);
+    let mut hook = backend::install_hook();
+    let result = backend::wait(&mut hook);
+    backend::delete_hook(hook);
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+pub fn lint_config(path: PathBuf) -> ExitCode {
+    let profiles = match config::load(&path) {
+        Ok(profiles) => profiles,
+        Err(err) => {
+            eprintln!("{}", err);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let depth = profiles.chain_depth();
+    let mut count = 0;
+    for profile in profiles.list() {
+        count += report(&profile.name, &profile.mappings, depth);
+        for layer in profile.layers.iter() {
+            let name = format!("{}/{}", profile.name, layer.name);
+            count += report(&name, &layer.mappings, depth);
+        }
+    }
+    if count > 0 {
+        println!("{} problems found", count);
+        return ExitCode::FAILURE;
+    }
+    println!("No problems found");
+    ExitCode::SUCCESS
+}
+
+fn report(name: &str, mappings: &[Mapping], chain_depth: u32) -> usize {
+    let warnings = lint(mappings, chain_depth);
+    for (index, warning) in warnings.iter() {
+        println!(
+            "{}: {}: {}",
+            name,
+            describe(mappings, *index),
+            warning.message(mappings)
+        );
+    }
+    warnings.len()
+}
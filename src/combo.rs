@@ -0,0 +1,216 @@
+use std::ops::Not;
+
+use crate::{engine::KeyEvent, keys::Mapping, vk_table::VirtualKey};
+
+/// Presses of combo keys, waiting for a combo to complete.
+#[derive(Debug)]
+pub struct PendingCombo {
+    pub events: Vec<KeyEvent>,
+}
+
+#[derive(Debug)]
+pub enum ComboDecision {
+    /// The event was consumed or buffered.
+    Wait,
+    /// The mapping of the completed combo, the event still needs to be handled if not consumed.
+    Trigger {
+        mapping: Box<Mapping>,
+        consumed: bool,
+    },
+    /// The buffered events and then the event itself need to be handled unchanged.
+    Fail,
+}
+
+impl PendingCombo {
+    pub fn new(event: KeyEvent) -> Self {
+        Self {
+            events: vec![event],
+        }
+    }
+
+    pub fn keys(&self) -> Vec<VirtualKey> {
+        self.events.iter().map(|event| event.key).collect()
+    }
+
+    /// Advance with the next event, `combos` are checked in order and larger combos are preferred.
+    pub fn decide(&mut self, event: KeyEvent, combos: &[&Mapping]) -> ComboDecision {
+        let elapsed = event.time.wrapping_sub(self.events[0].time);
+        let mut keys = self.keys();
+
+        if event.state.pressed() && keys.contains(&event.key).not() {
+            keys.push(event.key);
+            let candidates = candidates(combos, &keys, elapsed);
+            if candidates.is_empty().not() {
+                self.events.push(event);
+                if candidates.iter().all(|mapping| is_complete(mapping, &keys)) {
+                    return ComboDecision::Trigger {
+                        mapping: Box::new(candidates[0].clone()),
+                        consumed: true,
+                    };
+                }
+                return ComboDecision::Wait;
+            }
+            keys.pop();
+        } else if event.state.pressed() && candidates(combos, &keys, elapsed).is_empty().not() {
+            // autorepeat while a combo can still complete
+            return ComboDecision::Wait;
+        }
+
+        self.end(combos, &keys, false)
+    }
+
+    /// Decide once no combo can complete in time anymore, `None` while one still can.
+    pub fn expire(&self, time: u32, combos: &[&Mapping]) -> Option<ComboDecision> {
+        let elapsed = time.wrapping_sub(self.events[0].time);
+        let keys = self.keys();
+        if candidates(combos, &keys, elapsed).is_empty().not() {
+            return None;
+        }
+        Some(self.end(combos, &keys, true))
+    }
+
+    /// Trigger the combo of exactly the pressed keys or fail.
+    fn end(&self, combos: &[&Mapping], keys: &[VirtualKey], consumed: bool) -> ComboDecision {
+        let complete = combos.iter().find(|mapping| is_complete(mapping, keys));
+        match complete {
+            Some(mapping) => ComboDecision::Trigger {
+                mapping: Box::new((*mapping).clone()),
+                consumed,
+            },
+            None => ComboDecision::Fail,
+        }
+    }
+}
+
+/// Combos containing all the keys, which can still complete in time.
+fn candidates<'a>(combos: &[&'a Mapping], keys: &[VirtualKey], elapsed: u32) -> Vec<&'a Mapping> {
+    combos
+        .iter()
+        .copied()
+        .filter(|mapping| {
+            mapping.combo().is_some_and(|combo| {
+                elapsed <= combo.term && keys.iter().all(|key| combo.keys.contains(key))
+            })
+        })
+        .collect()
+}
+
+fn is_complete(mapping: &Mapping, keys: &[VirtualKey]) -> bool {
+    mapping.combo().is_some_and(|combo| {
+        combo.keys.len() == keys.len() && keys.iter().all(|key| combo.keys.contains(key))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::{KeyState, MappingData};
+
+    fn combos(json: &str) -> Vec<Mapping> {
+        serde_json::from_str::<Vec<MappingData>>(json)
+            .unwrap()
+            .into_iter()
+            .map(Mapping::from)
+            .collect()
+    }
+
+    fn event(name: &str, state: KeyState, time: u32) -> KeyEvent {
+        KeyEvent {
+            key: VirtualKey::from_name(name).unwrap(),
+            state,
+            injected: false,
+            time,
+        }
+    }
+
+    /// Number of keys of the triggered combo and if the event was consumed.
+    fn triggered(decision: ComboDecision) -> Option<(usize, bool)> {
+        match decision {
+            ComboDecision::Trigger { mapping, consumed } => {
+                Some((mapping.combo()?.keys.len(), consumed))
+            }
+            _ => None,
+        }
+    }
+
+    const OVERLAPPING: &str = r#"[
+        { "combo": ["J", "K"], "output": "Esc" },
+        { "combo": ["J", "K", "L"], "output": "Enter" }
+    ]"#;
+
+    #[test]
+    fn completed_combo() {
+        let mappings = combos(r#"[{ "combo": ["J", "K"], "output": "Esc" }]"#);
+        let combos = mappings.iter().collect::<Vec<_>>();
+        let mut pending = PendingCombo::new(event("K", KeyState::Pressed, 0));
+        let decision = pending.decide(event("J", KeyState::Pressed, 20), &combos);
+        assert_eq!(triggered(decision), Some((2, true)));
+    }
+
+    #[test]
+    fn failed_combo_keeps_the_order() {
+        let mappings = combos(r#"[{ "combo": ["J", "K", "L"], "output": "Esc" }]"#);
+        let combos = mappings.iter().collect::<Vec<_>>();
+        let mut pending = PendingCombo::new(event("J", KeyState::Pressed, 0));
+        let decision = pending.decide(event("K", KeyState::Pressed, 10), &combos);
+        assert!(matches!(decision, ComboDecision::Wait));
+        let decision = pending.decide(event("A", KeyState::Pressed, 20), &combos);
+        assert!(matches!(decision, ComboDecision::Fail));
+        assert_eq!(pending.keys(), [VirtualKey(0x4A), VirtualKey(0x4B)]);
+    }
+
+    #[test]
+    fn failed_combo_after_the_term() {
+        let mappings = combos(r#"[{ "combo": ["J", "K"], "output": "Esc" }]"#);
+        let combos = mappings.iter().collect::<Vec<_>>();
+        let pending = PendingCombo::new(event("J", KeyState::Pressed, 0));
+        assert!(pending.expire(30, &combos).is_none());
+        assert!(matches!(
+            pending.expire(31, &combos),
+            Some(ComboDecision::Fail)
+        ));
+    }
+
+    #[test]
+    fn overlapping_combos_prefer_more_keys() {
+        let mappings = combos(OVERLAPPING);
+        let combos = mappings.iter().collect::<Vec<_>>();
+        let mut pending = PendingCombo::new(event("J", KeyState::Pressed, 0));
+        let decision = pending.decide(event("K", KeyState::Pressed, 5), &combos);
+        assert!(matches!(decision, ComboDecision::Wait));
+        let decision = pending.decide(event("L", KeyState::Pressed, 10), &combos);
+        assert_eq!(triggered(decision), Some((3, true)));
+    }
+
+    #[test]
+    fn overlapping_combos_fall_back_to_the_smaller() {
+        let mappings = combos(OVERLAPPING);
+        let combos = mappings.iter().collect::<Vec<_>>();
+        let mut pending = PendingCombo::new(event("J", KeyState::Pressed, 0));
+        pending.decide(event("K", KeyState::Pressed, 5), &combos);
+        let decision = pending.decide(event("A", KeyState::Pressed, 10), &combos);
+        assert_eq!(triggered(decision), Some((2, false)));
+
+        let mut pending = PendingCombo::new(event("J", KeyState::Pressed, 0));
+        pending.decide(event("K", KeyState::Pressed, 5), &combos);
+        assert!(pending.expire(20, &combos).is_none());
+        assert_eq!(
+            triggered(pending.expire(31, &combos).unwrap()),
+            Some((2, true))
+        );
+    }
+
+    #[test]
+    fn release_before_completion() {
+        let mappings = combos(OVERLAPPING);
+        let combos = mappings.iter().collect::<Vec<_>>();
+        let mut pending = PendingCombo::new(event("J", KeyState::Pressed, 0));
+        let decision = pending.decide(event("J", KeyState::Released, 10), &combos);
+        assert!(matches!(decision, ComboDecision::Fail));
+
+        let mut pending = PendingCombo::new(event("J", KeyState::Pressed, 0));
+        pending.decide(event("K", KeyState::Pressed, 5), &combos);
+        let decision = pending.decide(event("K", KeyState::Released, 10), &combos);
+        assert_eq!(triggered(decision), Some((2, false)));
+    }
+}
@@ -0,0 +1,67 @@
+use std::{ops::Not, path::Path};
+
+use super::ConfigError;
+use crate::{
+    keys::{Mapping, Side, Stroke},
+    profile::{Profile, Profiles},
+    vk_table::VirtualKey,
+};
+
+#[derive(serde::Deserialize)]
+struct StrokeData {
+    key: u16,
+    keyboard: Vec<u8>,
+}
+
+#[derive(serde::Deserialize)]
+struct MappingData {
+    input: Option<StrokeData>,
+    output: Option<StrokeData>,
+}
+
+impl From<StrokeData> for Stroke {
+    fn from(stroke_data: StrokeData) -> Self {
+        Stroke::new(
+            stroke_data
+                .keyboard
+                .into_iter()
+                .map(|v| VirtualKey(v as u16))
+                .collect(),
+            VirtualKey(stroke_data.key),
+        )
+    }
+}
+
+impl From<MappingData> for Mapping {
+    fn from(mapping_data: MappingData) -> Self {
+        let mut mapping = Mapping::new_empty();
+        if let Some(input) = mapping_data.input {
+            mapping.update(Side::Input, input.into());
+        }
+        if let Some(output) = mapping_data.output {
+            mapping.update(Side::Output, output.into());
+        }
+        mapping
+    }
+}
+
+pub fn load(path: &Path, text: &str) -> Result<Profiles, ConfigError> {
+    let data = serde_json::from_str::<Vec<MappingData>>(text)
+        .map_err(|err| ConfigError::json(path, err))?;
+    let mappings = data.into_iter().map(Mapping::from).collect::<Vec<_>>();
+
+    let unknown = mappings
+        .iter()
+        .flat_map(|mapping| [mapping.get(Side::Input), mapping.get(Side::Output)])
+        .flatten()
+        .flat_map(|stroke| stroke.keys())
+        .find(|key| key.is_known().not());
+    if let Some(key) = unknown {
+        return Err(ConfigError::UnknownKey {
+            path: path.to_path_buf(),
+            key: key.0,
+        });
+    }
+    let profile = Profile::new("Default".into(), mappings);
+    Ok(Profiles::new(vec![profile], None))
+}
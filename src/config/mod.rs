@@ -0,0 +1,298 @@
+use std::{
+    ffi::OsString,
+    fmt,
+    fs::File,
+    io::{self, BufWriter, Write},
+    ops::Not,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    keys::{MappingData, StrokeData},
+    profile::{Profile, ProfileData, Profiles},
+};
+
+/// Unversioned format with numeric key codes, migrated when loaded.
+mod legacy;
+mod toml;
+
+#[derive(Debug)]
+pub enum ConfigError {
+    NotFound(PathBuf),
+    PermissionDenied(PathBuf),
+    Parse {
+        path: PathBuf,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    UnknownKey {
+        path: PathBuf,
+        key: u16,
+    },
+    UnsupportedVersion {
+        path: PathBuf,
+        version: u32,
+    },
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+}
+
+impl ConfigError {
+    pub fn io(path: &Path, source: io::Error) -> Self {
+        let path = path.to_path_buf();
+        match source.kind() {
+            io::ErrorKind::NotFound => Self::NotFound(path),
+            io::ErrorKind::PermissionDenied => Self::PermissionDenied(path),
+            _ => Self::Io { path, source },
+        }
+    }
+
+    fn json(path: &Path, source: serde_json::Error) -> Self {
+        if source.is_io() {
+            return Self::io(path, source.into());
+        }
+        Self::parse(path, source.line(), source.column(), source.to_string())
+    }
+
+    fn toml(path: &Path, text: &str, source: toml_edit::de::Error) -> Self {
+        let offset = source.span().map(|span| span.start).unwrap_or(0);
+        let before = &text[..offset];
+        let line = before.matches('\n').count() + 1;
+        let column = before.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+        Self::parse(path, line, column, source.message().to_string())
+    }
+
+    fn yaml(path: &Path, source: serde_yaml::Error) -> Self {
+        let (line, column) = match source.location() {
+            Some(location) => (location.line(), location.column()),
+            None => (0, 0),
+        };
+        Self::parse(path, line, column, source.to_string())
+    }
+
+    fn parse(path: &Path, line: usize, column: usize, message: String) -> Self {
+        // the position is stored separately
+        let message = match message.rsplit_once(" at line ") {
+            Some((message, _)) => message.to_string(),
+            None => message,
+        };
+        Self::Parse {
+            path: path.to_path_buf(),
+            line,
+            column,
+            message,
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound(path) => write!(f, "File {:?} not found", path),
+            Self::PermissionDenied(path) => write!(f, "Permission denied for {:?}", path),
+            Self::Parse {
+                path,
+                line,
+                column,
+                message,
+            } => write!(
+                f,
+                "Invalid configuration {:?} at line {}, column {}: {}",
+                path, line, column, message
+            ),
+            Self::UnknownKey { path, key } => {
+                write!(f, "Unknown key code {} in {:?}", key, path)
+            }
+            Self::UnsupportedVersion { path, version } => write!(
+                f,
+                "Configuration {:?} has version {}, only {} is supported",
+                path, version, VERSION
+            ),
+            Self::Io { path, source } => write!(f, "Failed to access {:?}: {}", path, source),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// Version written by [`save`], files with a plain list of mappings are treated as version 0.
+///
+/// Version 1 stored a single list of mappings, which is loaded as the only profile.
+pub const VERSION: u32 = 2;
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct ConfigData {
+    version: u32,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    mappings: Vec<MappingData>,
+    #[serde(default)]
+    profiles: Vec<ProfileData>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cycle: Option<StrokeData>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    chain_depth: Option<u32>,
+    #[serde(default, skip_serializing_if = "Not::not")]
+    skip_injected: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl Format {
+    /// Select the format by file extension, JSON is used for unknown extensions.
+    pub fn from_path(path: &Path) -> Self {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or_default();
+        match extension.to_ascii_lowercase().as_str() {
+            "toml" => Self::Toml,
+            "yaml" | "yml" => Self::Yaml,
+            _ => Self::Json,
+        }
+    }
+}
+
+pub fn load(path: &Path) -> Result<Profiles, ConfigError> {
+    let text = std::fs::read_to_string(path).map_err(|err| ConfigError::io(path, err))?;
+    let format = Format::from_path(path);
+    if format == Format::Json && text.trim_start().starts_with('[') {
+        return legacy::load(path, &text);
+    }
+
+    let data = match format {
+        Format::Json => {
+            serde_json::from_str::<ConfigData>(&text).map_err(|err| ConfigError::json(path, err))?
+        }
+        Format::Toml => toml_edit::de::from_str::<ConfigData>(&text)
+            .map_err(|err| ConfigError::toml(path, &text, err))?,
+        Format::Yaml => from_yaml(&text).map_err(|err| ConfigError::yaml(path, err))?,
+    };
+
+    if data.version > VERSION {
+        return Err(ConfigError::UnsupportedVersion {
+            path: path.to_path_buf(),
+            version: data.version,
+        });
+    }
+
+    let mut profiles = data
+        .profiles
+        .into_iter()
+        .map(Profile::from)
+        .collect::<Vec<_>>();
+    if data.mappings.is_empty().not() {
+        let mappings = data.mappings.into_iter().map(Into::into).collect();
+        profiles.insert(0, Profile::new("Default".into(), mappings));
+    }
+    let mut profiles = Profiles::new(profiles, data.cycle.map(Into::into));
+    profiles.set_chain_depth(data.chain_depth);
+    profiles.set_skip_injected(data.skip_injected);
+    Ok(profiles)
+}
+
+pub fn save(path: &Path, profiles: &Profiles) -> Result<(), ConfigError> {
+    let data = ConfigData {
+        version: VERSION,
+        mappings: Vec::new(),
+        profiles: profiles.list().iter().cloned().map(Into::into).collect(),
+        cycle: profiles.cycle().cloned().map(Into::into),
+        chain_depth: profiles.configured_chain_depth(),
+        skip_injected: profiles.skip_injected(),
+    };
+    let text = match Format::from_path(path) {
+        Format::Json => serde_json::to_string_pretty(&data).map_err(io::Error::from),
+        Format::Toml => toml::to_string(path, &data),
+        Format::Yaml => to_yaml(&data),
+    };
+    text.and_then(|text| write_atomic(path, |file| file.write_all(text.as_bytes())))
+        .map_err(|err| ConfigError::io(path, err))
+}
+
+/// Enums are written as maps like `stroke: Control+K` instead of YAML tags,
+/// the same shape as in JSON and TOML.
+fn from_yaml(text: &str) -> Result<ConfigData, serde_yaml::Error> {
+    serde_yaml::with::singleton_map_recursive::deserialize(serde_yaml::Deserializer::from_str(text))
+}
+
+fn to_yaml(data: &ConfigData) -> io::Result<String> {
+    let mut text = Vec::new();
+    let mut serializer = serde_yaml::Serializer::new(&mut text);
+    serde_yaml::with::singleton_map_recursive::serialize(data, &mut serializer)
+        .map_err(io::Error::other)?;
+    drop(serializer);
+    String::from_utf8(text).map_err(io::Error::other)
+}
+
+/// Write into a temporary file next to `path` and replace `path` only after the write completed.
+pub fn write_atomic(
+    path: &Path,
+    write: impl FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut temp_name = path.file_name().map(OsString::from).unwrap_or_default();
+    temp_name.push(".tmp");
+    let temp_path = path.with_file_name(temp_name);
+
+    let mut file = BufWriter::new(File::create(&temp_path)?);
+    let result = write(&mut file)
+        .and_then(|_| file.flush())
+        .and_then(|_| file.get_ref().sync_all());
+    drop(file);
+    if let Err(err) = result {
+        let _ = std::fs::remove_file(&temp_path);
+        return Err(err);
+    }
+    std::fs::rename(&temp_path, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn yaml_uses_the_json_shape() {
+        let path = std::env::temp_dir().join("ein-key-round-trip.yaml");
+        let text = r#"version: 2
+profiles:
+- name: Default
+  mappings:
+  - input: Menu+M
+    macro:
+    - stroke: Control+K
+    - wait: 30
+  - input: Space
+    layer:
+      momentary: nav
+  layers:
+  - name: nav
+    mappings:
+    - input: H
+      output: Left
+"#;
+        std::fs::write(&path, text).unwrap();
+        let profiles = load(&path).unwrap();
+        save(&path, &profiles).unwrap();
+        let saved = std::fs::read_to_string(&path).unwrap();
+        let reloaded = load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(saved, text);
+        let mappings = &reloaded.list()[0].mappings;
+        assert_eq!(mappings[0].steps().len(), 2);
+        assert!(mappings[1].layer().is_some());
+    }
+}
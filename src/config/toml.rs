@@ -0,0 +1,212 @@
+use std::{io, ops::Not, path::Path};
+
+use toml_edit::{value, ArrayOfTables, DocumentMut, Item, Table};
+
+use super::ConfigData;
+use crate::keys::MappingData;
+
+/// Serialize into the existing document at `path`, so comments and formatting are kept.
+pub fn to_string(path: &Path, data: &ConfigData) -> io::Result<String> {
+    let mut document = std::fs::read_to_string(path)
+        .ok()
+        .and_then(|text| text.parse::<DocumentMut>().ok())
+        .unwrap_or_default();
+    let root = document.as_table_mut();
+
+    set(root, "version", value(i64::from(data.version)));
+    set_optional(root, "cycle", data.cycle.clone().map(String::from));
+    match data.chain_depth {
+        Some(chain_depth) => set(root, "chain_depth", value(i64::from(chain_depth))),
+        None => {
+            root.remove("chain_depth");
+        }
+    }
+    match data.skip_injected {
+        true => set(root, "skip_injected", value(true)),
+        false => {
+            root.remove("skip_injected");
+        }
+    }
+
+    // version 1 stored the mappings of the only profile at the root
+    let mut legacy_mappings = take_tables(root.remove("mappings"));
+    let mut old_profiles = take_tables(root.remove("profiles"));
+    let mut position = old_profiles
+        .iter()
+        .chain(legacy_mappings.iter())
+        .filter_map(Table::position)
+        .min()
+        .unwrap_or(1);
+
+    let mut profiles = ArrayOfTables::new();
+    for profile in data.profiles.iter() {
+        let mut table = take_matching(&mut old_profiles, "name", &profile.name).unwrap_or_default();
+        set(&mut table, "name", value(profile.name.as_str()));
+        set_optional(
+            &mut table,
+            "hotkey",
+            profile.hotkey.clone().map(String::from),
+        );
+        table.set_position(position);
+        position += 1;
+
+        let mut old_mappings = take_tables(table.remove("mappings"));
+        if old_mappings.is_empty() {
+            old_mappings = std::mem::take(&mut legacy_mappings);
+        }
+        set_mappings(&mut table, old_mappings, &profile.mappings, &mut position)?;
+
+        let mut old_layers = take_tables(table.remove("layers"));
+        let mut layers = ArrayOfTables::new();
+        for layer in profile.layers.iter() {
+            let mut layer_table =
+                take_matching(&mut old_layers, "name", &layer.name).unwrap_or_default();
+            set(&mut layer_table, "name", value(layer.name.as_str()));
+            layer_table.set_position(position);
+            position += 1;
+
+            let old_mappings = take_tables(layer_table.remove("mappings"));
+            set_mappings(
+                &mut layer_table,
+                old_mappings,
+                &layer.mappings,
+                &mut position,
+            )?;
+            layers.push(layer_table);
+        }
+        if layers.is_empty().not() {
+            table.insert("layers", Item::ArrayOfTables(layers));
+        }
+        profiles.push(table);
+    }
+    root.insert("profiles", Item::ArrayOfTables(profiles));
+
+    Ok(document.to_string())
+}
+
+/// Insert the mappings into `table`, reusing the old table with the same input or combo for each.
+fn set_mappings(
+    table: &mut Table,
+    mut old_mappings: Vec<Table>,
+    mappings: &[MappingData],
+    position: &mut usize,
+) -> io::Result<()> {
+    let mut array = ArrayOfTables::new();
+    for mapping in mappings.iter() {
+        let new = toml_edit::ser::to_document(mapping)
+            .map_err(io::Error::other)?
+            .into_table();
+        let old = mapping_trigger(&new).and_then(|trigger| {
+            let index = old_mappings
+                .iter()
+                .position(|old| mapping_trigger(old).as_ref() == Some(&trigger))?;
+            Some(old_mappings.remove(index))
+        });
+        let mut mapping_table = match old {
+            Some(mut mapping_table) => {
+                update(&mut mapping_table, new);
+                mapping_table
+            }
+            None => new,
+        };
+        mapping_table.set_position(*position);
+        *position += 1;
+        array.push(mapping_table);
+    }
+    if array.is_empty().not() {
+        table.insert("mappings", Item::ArrayOfTables(array));
+    }
+    Ok(())
+}
+
+fn take_tables(item: Option<Item>) -> Vec<Table> {
+    item.and_then(|item| item.into_array_of_tables().ok())
+        .map(|tables| tables.into_iter().collect())
+        .unwrap_or_default()
+}
+
+fn take_matching(tables: &mut Vec<Table>, key: &str, value: &str) -> Option<Table> {
+    let index = tables
+        .iter()
+        .position(|table| table.get(key).and_then(Item::as_str) == Some(value))?;
+    Some(tables.remove(index))
+}
+
+/// Strokes of the input or keys of the combo, `None` for mappings without either.
+fn mapping_trigger(table: &Table) -> Option<(&'static str, Vec<String>)> {
+    ["input", "combo"].into_iter().find_map(|key| {
+        let item = table.get(key)?;
+        let names = match item.as_array() {
+            Some(array) => array
+                .iter()
+                .map(|value| value.as_str().map(String::from))
+                .collect::<Option<Vec<_>>>()?,
+            None => vec![item.as_str()?.to_owned()],
+        };
+        Some((key, names))
+    })
+}
+
+fn update(table: &mut Table, new: Table) {
+    table.retain(|key, _| new.contains_key(key));
+    for (key, item) in new {
+        set(table, &key, item);
+    }
+}
+
+fn set_optional(table: &mut Table, key: &str, text: Option<String>) {
+    match text {
+        Some(text) => set(table, key, value(text)),
+        None => {
+            table.remove(key);
+        }
+    }
+}
+
+/// Replace the value but keep the comments around the old one.
+fn set(table: &mut Table, key: &str, mut item: Item) {
+    let Some(old) = table.get_mut(key) else {
+        table.insert(key, item);
+        return;
+    };
+    if let (Some(old_value), Some(value)) = (old.as_value(), item.as_value_mut()) {
+        *value.decor_mut() = old_value.decor().clone();
+    }
+    *old = item;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Not;
+
+    use crate::config;
+
+    #[test]
+    fn comments_stay_with_their_mapping() {
+        let path = std::env::temp_dir().join("ein-key-comments.toml");
+        let text = r#"version = 2
+
+[[profiles]]
+name = "Default"
+
+# save
+[[profiles.mappings]]
+input = ["Control+X", "Control+S"]
+output = "F2"
+
+# escape
+[[profiles.mappings]]
+combo = ["J", "K"]
+output = "Escape"
+"#;
+        std::fs::write(&path, text).unwrap();
+        let mut profiles = config::load(&path).unwrap();
+        profiles.list_mut()[0].mappings.remove(0);
+        config::save(&path, &profiles).unwrap();
+
+        let saved = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(saved.contains("# save").not());
+        assert!(saved.contains("# escape"));
+    }
+}
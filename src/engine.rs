@@ -0,0 +1,1536 @@
+use std::{cell::OnceCell, ops::Not};
+
+use crate::{
+    combo::{ComboDecision, PendingCombo},
+    keys::{self, KeyState, Mapping, Side, Sticky, Stroke, Trigger},
+    layer::LayerStack,
+    macros::Step,
+    profile::Profiles,
+    scope::{NoWindow, WindowInfo, WindowProvider},
+    sequence::{self, MappingIndex, PendingSequence, SequenceTrie},
+    sticky::Latch,
+    tap_hold::{Decision, PendingHold},
+    vk_table::*,
+};
+
+/// Milliseconds between the calls of [`RemapEngine::tick`] by the backends.
+pub const TICK_INTERVAL: u32 = 50;
+
+/// Key event as reported by the platform backend.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub key: VirtualKey,
+    pub state: KeyState,
+    /// Sent by another program, the own outputs are filtered by the backend.
+    pub injected: bool,
+    /// Milliseconds, only the difference between events is meaningful.
+    pub time: u32,
+}
+
+/// Key event the platform backend should send instead of the original one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputEvent {
+    pub key: VirtualKey,
+    pub state: KeyState,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Status {
+    Intercept,
+    Allow,
+    Replace(Vec<OutputEvent>),
+    /// Outputs with waits in between, played off the hook thread.
+    Macro(Vec<MacroStep>),
+}
+
+impl Status {
+    /// Steps to play instead of the original event.
+    fn into_steps(self, event: KeyEvent) -> Vec<MacroStep> {
+        match self {
+            Self::Allow => vec![MacroStep::Send(vec![OutputEvent {
+                key: event.key,
+                state: event.state,
+            }])],
+            Self::Intercept => Vec::new(),
+            Self::Replace(outputs) => vec![MacroStep::Send(outputs)],
+            Self::Macro(steps) => steps,
+        }
+    }
+
+    fn from_steps(mut steps: Vec<MacroStep>) -> Self {
+        match steps.as_slice() {
+            [] => Self::Intercept,
+            [MacroStep::Send(_)] => match steps.pop() {
+                Some(MacroStep::Send(outputs)) => Self::Replace(outputs),
+                _ => unreachable!(),
+            },
+            _ => Self::Macro(steps),
+        }
+    }
+}
+
+/// Part of a macro, the outputs between waits are sent together.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MacroStep {
+    Send(Vec<OutputEvent>),
+    /// Characters typed independent of the keyboard layout.
+    Text(String),
+    /// Milliseconds.
+    Wait(u32),
+}
+
+fn append_steps(steps: &mut Vec<MacroStep>, new: Vec<MacroStep>) {
+    for step in new {
+        match (steps.last_mut(), step) {
+            (_, MacroStep::Send(outputs)) if outputs.is_empty() => {}
+            (Some(MacroStep::Send(last)), MacroStep::Send(outputs)) => last.extend(outputs),
+            (_, step) => steps.push(step),
+        }
+    }
+}
+
+/// What the repeats and the release of a key do, once a mapping consumed its press.
+#[derive(Debug)]
+enum Consumed {
+    /// Both are intercepted, like for profile hotkeys and completed sequences.
+    Intercept,
+    /// Output pressed for a remapped key, `None` for macros and text.
+    /// Repeats and the release use it even if the held modifiers changed since.
+    Output(Option<Stroke>),
+    /// Key of a triggered combo and its output, the first release of the keys ends the output.
+    Combo(Vec<VirtualKey>, Option<Stroke>),
+    /// Dual-role key decided as held and its pressed output.
+    Hold(Stroke),
+    /// The key activated a layer until its release.
+    Layer,
+    /// Held sticky key, the modifiers it pressed and if another key was pressed since.
+    Sticky {
+        sticky: Sticky,
+        pressed: Vec<VirtualKey>,
+        used: bool,
+    },
+    /// Input of a release triggered mapping, no other key was pressed since.
+    Release(Box<Mapping>),
+}
+
+/// Platform independent remapping, tracks the held keys and matches them against the mappings.
+#[derive(Debug)]
+pub struct RemapEngine {
+    keyboard: Vec<VirtualKey>,
+    profiles: Profiles,
+    windows: Box<dyn WindowProvider>,
+    combo: Option<PendingCombo>,
+    pending: Option<PendingHold>,
+    layers: LayerStack,
+    /// Pressed keys and the active layers at the time, releases use the same layers.
+    presses: Vec<(VirtualKey, Vec<usize>)>,
+    /// Built on first use and after the mappings change.
+    sequences: Option<SequenceTrie>,
+    sequence: Option<PendingSequence>,
+    /// Tapped sticky keys, their modifiers are added to the next key.
+    latches: Vec<Latch>,
+    /// Last tap of each key, cleared by other keys.
+    taps: Vec<(VirtualKey, u32)>,
+    /// Held keys whose press was consumed by a mapping.
+    consumed: Vec<(VirtualKey, Consumed)>,
+    /// Keys pressed while capturing since the keyboard was empty.
+    chord: Vec<VirtualKey>,
+}
+
+impl RemapEngine {
+    pub fn new(profiles: Profiles) -> Self {
+        Self {
+            keyboard: Vec::new(),
+            profiles,
+            windows: Box::new(NoWindow),
+            combo: None,
+            pending: None,
+            layers: LayerStack::default(),
+            presses: Vec::new(),
+            sequences: None,
+            sequence: None,
+            latches: Vec::new(),
+            taps: Vec::new(),
+            consumed: Vec::new(),
+            chord: Vec::new(),
+        }
+    }
+
+    pub fn set_window_provider(&mut self, windows: Box<dyn WindowProvider>) {
+        self.windows = windows;
+    }
+
+    pub fn profiles(&self) -> &Profiles {
+        &self.profiles
+    }
+
+    pub fn profiles_mut(&mut self) -> &mut Profiles {
+        self.reset_sequences();
+        &mut self.profiles
+    }
+
+    /// Mappings of the active profile.
+    pub fn mappings(&self) -> &[Mapping] {
+        &self.profiles.active().mappings
+    }
+
+    pub fn mappings_mut(&mut self) -> &mut Vec<Mapping> {
+        self.reset_sequences();
+        &mut self.profiles.active_mut().mappings
+    }
+
+    /// Strokes of the sequence typed so far.
+    pub fn pending_sequence(&self) -> Option<String> {
+        self.sequence.as_ref().map(ToString::to_string)
+    }
+
+    /// Resolve timed out combos, dual-role keys and sequences without waiting for the next key,
+    /// called every [`TICK_INTERVAL`].
+    pub fn tick(&mut self, time: u32) -> Vec<MacroStep> {
+        let mut steps = Vec::new();
+        if let Some(combo) = self.combo.take() {
+            match combo.expire(time, &self.combos()) {
+                Some(decision) => append_steps(&mut steps, self.resolve_combo(combo, decision)),
+                None => self.combo = Some(combo),
+            }
+        }
+        if let Some(pending) = self.pending.take_if(|pending| pending.timed_out(time)) {
+            append_steps(&mut steps, self.resolve_hold(pending, Decision::Hold));
+        }
+        if let Some(pending) = self.sequence.take_if(|pending| pending.timed_out(time)) {
+            let trie = self
+                .sequences
+                .get_or_insert_with(|| SequenceTrie::new(self.profiles.active()));
+            let complete = trie.node(&pending.path).mappings().to_vec();
+            append_steps(&mut steps, self.complete_sequence(pending, &complete));
+        }
+        steps
+    }
+
+    fn reset_sequences(&mut self) {
+        self.sequences = None;
+        self.sequence = None;
+    }
+
+    pub fn handle(&mut self, event: KeyEvent) -> Status {
+        if event.injected && self.profiles.skip_injected() {
+            return Status::Allow;
+        }
+
+        let Some(mut combo) = self.combo.take() else {
+            if self.starts_combo(event) {
+                self.combo = Some(PendingCombo::new(event));
+                return Status::Intercept;
+            }
+            return self.handle_hold(event);
+        };
+        let decision = combo.decide(event, &self.combos());
+        let consumed = match decision {
+            ComboDecision::Wait => {
+                self.combo = Some(combo);
+                return Status::Intercept;
+            }
+            ComboDecision::Trigger { consumed, .. } => consumed,
+            ComboDecision::Fail => false,
+        };
+        let mut steps = self.resolve_combo(combo, decision);
+        if consumed.not() {
+            append_steps(&mut steps, self.handle(event).into_steps(event));
+        }
+        Status::from_steps(steps)
+    }
+
+    /// Press the output of a triggered combo or replay the keys of a failed one.
+    fn resolve_combo(&mut self, combo: PendingCombo, decision: ComboDecision) -> Vec<MacroStep> {
+        let ComboDecision::Trigger { mapping, .. } = decision else {
+            let mut steps = Vec::new();
+            for event in combo.events {
+                append_steps(&mut steps, self.replay(event));
+            }
+            return steps;
+        };
+        let (steps, stroke) = self.press(&mapping, &[]);
+        let keys = combo.keys();
+        for key in keys.iter().copied() {
+            self.consumed
+                .push((key, Consumed::Combo(keys.clone(), stroke.clone())));
+        }
+        steps
+    }
+
+    fn starts_combo(&self, event: KeyEvent) -> bool {
+        if event.state.released()
+            || self.has_key(event.key)
+            || self.consumed.iter().any(|(key, _)| *key == event.key)
+            || self
+                .pending
+                .as_ref()
+                .is_some_and(|pending| pending.key == event.key)
+        {
+            return false;
+        }
+        self.combos().iter().any(|mapping| {
+            mapping
+                .combo()
+                .is_some_and(|combo| combo.keys.contains(&event.key))
+        })
+    }
+
+    /// Combo mappings of the active layers and the profile, in lookup order.
+    fn combos(&self) -> Vec<&Mapping> {
+        let window = OnceCell::new();
+        let profile = self.profiles.active();
+        self.layers
+            .indices()
+            .into_iter()
+            .filter_map(|index| profile.layers.get(index))
+            .flat_map(|layer| layer.mappings.iter())
+            .chain(profile.mappings.iter())
+            .filter(|mapping| mapping.combo().is_some() && self.in_scope(mapping, &window))
+            .collect()
+    }
+
+    fn handle_hold(&mut self, event: KeyEvent) -> Status {
+        let Some(mut pending) = self.pending.take() else {
+            return self.process(event);
+        };
+        let decision = pending.decide(event);
+        if decision == Decision::Wait {
+            self.pending = Some(pending);
+            return Status::Intercept;
+        }
+        let mut steps = self.resolve_hold(pending, decision);
+        if decision == Decision::Hold {
+            append_steps(&mut steps, self.replay(event));
+        }
+        Status::from_steps(steps)
+    }
+
+    /// Send the tap or press the hold of a decided dual-role key, then replay the buffered events.
+    fn resolve_hold(&mut self, pending: PendingHold, decision: Decision) -> Vec<MacroStep> {
+        let outputs = match (decision, &pending.tap) {
+            (Decision::Hold, _) => self.press_hold(pending.key, pending.hold.stroke),
+            (_, Some(stroke)) => {
+                let mut outputs = self.press_outputs(stroke);
+                outputs.extend(self.release_outputs(stroke));
+                outputs
+            }
+            (_, None) => Vec::new(),
+        };
+
+        let mut steps = vec![MacroStep::Send(outputs)];
+        for event in pending.buffered {
+            append_steps(&mut steps, self.replay(event));
+        }
+        steps
+    }
+
+    fn process(&mut self, event: KeyEvent) -> Status {
+        self.latches.retain(|latch| latch.expired(event.time).not());
+        if let Some(status) = self.release_consumed(event) {
+            return status;
+        }
+        if event.state.pressed() && self.has_key(event.key).not() {
+            for (_, consumed) in self.consumed.iter_mut() {
+                if let Consumed::Sticky { used, .. } = consumed {
+                    *used = true;
+                }
+            }
+            let outputs = self.interrupt_releases();
+            if outputs.is_empty().not() {
+                let mut steps = vec![MacroStep::Send(outputs)];
+                append_steps(&mut steps, self.process(event).into_steps(event));
+                return Status::from_steps(steps);
+            }
+        }
+        if event.state.pressed() {
+            let layers = self.layers.indices();
+            if self.activate_layer(&layers, event.key) {
+                return Status::Intercept;
+            }
+            if let Some(pending) = self.start_hold(&layers, event) {
+                self.pending = Some(pending);
+                return Status::Intercept;
+            }
+        }
+        if let Some(status) = self.advance_sequence(event) {
+            return status;
+        }
+
+        let layers = self.event_layers(event);
+        if event.state.released() {
+            self.release_key(event.key);
+        }
+        if let Some(status) = self.sticky(&layers, event) {
+            return status;
+        }
+
+        let latched = self.press_latched(event);
+        let status = match self.switch_profile(event) {
+            Some(status) => status,
+            None => match self.trigger(&layers, event, &latched) {
+                Some(status) => status,
+                None => self.remap(&layers, event, &latched),
+            },
+        };
+        let status = self.release_latched(latched, status, event);
+
+        if event.state.pressed() {
+            self.press_key(event.key);
+        }
+        status
+    }
+
+    /// Repeats and the release of consumed keys, `None` for the other keys.
+    fn release_consumed(&mut self, event: KeyEvent) -> Option<Status> {
+        let index = self
+            .consumed
+            .iter()
+            .position(|(key, _)| *key == event.key)?;
+        if event.state.pressed() {
+            let status = match &self.consumed[index].1 {
+                // the held keys are already set up by the first press
+                Consumed::Output(Some(stroke)) => Status::Replace(vec![OutputEvent {
+                    key: stroke.key(),
+                    state: KeyState::Pressed,
+                }]),
+                Consumed::Release(_) if event.key.is_modifier() => Status::Allow,
+                _ => Status::Intercept,
+            };
+            return Some(status);
+        }
+
+        let (_, consumed) = self.consumed.remove(index);
+        self.presses.retain(|(key, _)| *key != event.key);
+        // only intercepted, remapped and release triggered keys are tracked as held
+        if let Consumed::Intercept | Consumed::Output(_) | Consumed::Release(_) = consumed {
+            self.release_key(event.key);
+        }
+        let status = match consumed {
+            Consumed::Intercept => Status::Intercept,
+            Consumed::Output(stroke) => Status::from_steps(self.release(stroke.as_ref())),
+            Consumed::Combo(keys, stroke) => {
+                for (key, consumed) in self.consumed.iter_mut() {
+                    if keys.contains(key) {
+                        *consumed = Consumed::Intercept;
+                    }
+                }
+                Status::from_steps(self.release(stroke.as_ref()))
+            }
+            Consumed::Hold(stroke) => {
+                let mut outputs = Vec::new();
+                for key in stroke.keys().rev() {
+                    self.release_key(key);
+                    outputs.push(OutputEvent {
+                        key,
+                        state: KeyState::Released,
+                    });
+                }
+                Status::Replace(outputs)
+            }
+            Consumed::Layer => {
+                self.layers.release(event.key);
+                Status::Intercept
+            }
+            Consumed::Sticky {
+                sticky,
+                pressed,
+                used,
+            } => {
+                let mut outputs = Vec::new();
+                for key in pressed.into_iter().rev() {
+                    self.release_key(key);
+                    outputs.push(OutputEvent {
+                        key,
+                        state: KeyState::Released,
+                    });
+                }
+                if used.not() {
+                    self.latch(event.key, sticky, event.time);
+                }
+                Status::Replace(outputs)
+            }
+            Consumed::Release(mapping) => {
+                // a lone Alt or Win would open the menu bar or start menu, like AutoHotkey a
+                // key without function is tapped before
+                let mut steps = Vec::new();
+                if matches!(event.key.generic(), VK_MENU | VK_LWIN | VK_RWIN) {
+                    steps.push(MacroStep::Send(vec![
+                        OutputEvent {
+                            key: VK_MASK,
+                            state: KeyState::Pressed,
+                        },
+                        OutputEvent {
+                            key: VK_MASK,
+                            state: KeyState::Released,
+                        },
+                    ]));
+                }
+                // modifiers still work together with other keys
+                if event.key.is_modifier() {
+                    append_steps(&mut steps, Status::Allow.into_steps(event));
+                }
+                append_steps(&mut steps, self.tap(&mapping));
+                Status::from_steps(steps)
+            }
+        };
+        Some(status)
+    }
+
+    /// Another key cancels the release triggered mappings, the held keys are pressed as typed.
+    fn interrupt_releases(&mut self) -> Vec<OutputEvent> {
+        let mut outputs = Vec::new();
+        self.consumed.retain(|(key, consumed)| {
+            let Consumed::Release(_) = consumed else {
+                return true;
+            };
+            // the press of modifiers was already allowed
+            if key.is_modifier().not() {
+                outputs.push(OutputEvent {
+                    key: *key,
+                    state: KeyState::Pressed,
+                });
+            }
+            false
+        });
+        outputs
+    }
+
+    /// Handle a delayed event, the original event can no longer be allowed.
+    fn replay(&mut self, event: KeyEvent) -> Vec<MacroStep> {
+        self.handle_hold(event).into_steps(event)
+    }
+
+    /// Layers to resolve the event with, presses use the active layers and consume one-shot layers.
+    fn event_layers(&mut self, event: KeyEvent) -> Vec<usize> {
+        let index = self.presses.iter().position(|(key, _)| *key == event.key);
+        match (event.state, index) {
+            (KeyState::Pressed, Some(index)) => self.presses[index].1.clone(),
+            (KeyState::Released, Some(index)) => self.presses.remove(index).1,
+            (KeyState::Pressed, None) => {
+                let layers = self.layers.indices();
+                self.presses.push((event.key, layers.clone()));
+                self.layers.consume_one_shot();
+                layers
+            }
+            (KeyState::Released, None) => self.layers.indices(),
+        }
+    }
+
+    fn activate_layer(&mut self, layers: &[usize], key: VirtualKey) -> bool {
+        let Some(action) = self
+            .lookup(layers, key)
+            .and_then(|mapping| mapping.layer())
+            .cloned()
+        else {
+            return false;
+        };
+        match self.profiles.active().layer_index(action.name()) {
+            Some(index) => self.layers.activate(&action, index, key),
+            None => println!("Unknown layer '{}'", action.name()),
+        }
+        self.consumed.push((key, Consumed::Layer));
+        true
+    }
+
+    fn start_hold(&self, layers: &[usize], event: KeyEvent) -> Option<PendingHold> {
+        let mapping = self.lookup(layers, event.key)?;
+        let hold = mapping.hold()?.clone();
+        Some(PendingHold::new(
+            event,
+            mapping.get(Side::Output).cloned(),
+            hold,
+        ))
+    }
+
+    fn press_hold(&mut self, key: VirtualKey, stroke: Stroke) -> Vec<OutputEvent> {
+        let mut outputs = Vec::new();
+        for key in stroke.keys() {
+            self.press_key(key);
+            outputs.push(OutputEvent {
+                key,
+                state: KeyState::Pressed,
+            });
+        }
+        self.consumed.push((key, Consumed::Hold(stroke)));
+        outputs
+    }
+
+    /// Follow the sequence trie, the strokes of a prefix are intercepted until the sequence ends.
+    fn advance_sequence(&mut self, event: KeyEvent) -> Option<Status> {
+        if event.key.is_modifier() {
+            return None;
+        }
+
+        let trie = self
+            .sequences
+            .get_or_insert_with(|| SequenceTrie::new(self.profiles.active()));
+        let Some(mut pending) = self.sequence.take() else {
+            if event.state.released() || self.keyboard.contains(&event.key) {
+                return None;
+            }
+            let child = trie.step(&self.keyboard, event.key)?;
+            let stroke = Stroke::new(self.keyboard.clone(), event.key);
+            self.sequence = Some(PendingSequence::new(child, stroke, event.time));
+            return Some(Status::Intercept);
+        };
+
+        let node = trie.node(&pending.path);
+        if pending.timed_out(event.time) {
+            let complete = node.mappings().to_vec();
+            return Some(self.fail_sequence(pending, &complete, event));
+        }
+        if pending.held().contains(&event.key) {
+            if event.state.released() {
+                let stroke = Stroke::new(Vec::new(), event.key);
+                pending.events.push((stroke, KeyState::Released));
+            }
+            self.sequence = Some(pending);
+            return Some(Status::Intercept);
+        }
+        if event.state.released() {
+            self.sequence = Some(pending);
+            return None;
+        }
+
+        let Some(child) = node.step(&self.keyboard, event.key) else {
+            let complete = node.mappings().to_vec();
+            return Some(self.fail_sequence(pending, &complete, event));
+        };
+        let stroke = Stroke::new(self.keyboard.clone(), event.key);
+        pending.push(child, stroke, event.time);
+        let node = trie.node(&pending.path);
+        if node.is_leaf().not() {
+            self.sequence = Some(pending);
+            return Some(Status::Intercept);
+        }
+        let complete = node.mappings().to_vec();
+        Some(Status::from_steps(
+            self.complete_sequence(pending, &complete),
+        ))
+    }
+
+    /// Trigger the mapping of a complete shorter sequence or replay the prefix, then handle the event.
+    fn fail_sequence(
+        &mut self,
+        pending: PendingSequence,
+        complete: &[MappingIndex],
+        event: KeyEvent,
+    ) -> Status {
+        let mut steps = self.complete_sequence(pending, complete);
+        append_steps(&mut steps, self.process(event).into_steps(event));
+        Status::from_steps(steps)
+    }
+
+    fn complete_sequence(
+        &mut self,
+        pending: PendingSequence,
+        complete: &[MappingIndex],
+    ) -> Vec<MacroStep> {
+        let Some(mapping) = self.sequence_mapping(complete).cloned() else {
+            return vec![MacroStep::Send(self.replay_sequence(pending))];
+        };
+        for key in pending.held() {
+            self.consumed.push((key, Consumed::Intercept));
+        }
+        self.tap(&mapping)
+    }
+
+    /// Mapping of a complete sequence in lookup order, mappings of inactive layers are skipped.
+    fn sequence_mapping(&self, complete: &[MappingIndex]) -> Option<&Mapping> {
+        let window = OnceCell::new();
+        let profile = self.profiles.active();
+        self.layers
+            .indices()
+            .into_iter()
+            .map(Some)
+            .chain([None])
+            .find_map(|layer| {
+                complete
+                    .iter()
+                    .filter(|index| index.0 == layer)
+                    .filter_map(|index| sequence::mapping(profile, *index))
+                    .find(|mapping| self.in_scope(mapping, &window))
+            })
+    }
+
+    /// Send the prefix of a failed sequence as it was typed.
+    fn replay_sequence(&mut self, pending: PendingSequence) -> Vec<OutputEvent> {
+        let mut outputs = Vec::new();
+        for (stroke, state) in pending.events {
+            match state {
+                KeyState::Pressed => {
+                    outputs.extend(self.create_outputs(&stroke, KeyState::Pressed));
+                    self.press_key(stroke.key());
+                }
+                KeyState::Released => {
+                    self.release_key(stroke.key());
+                    outputs.push(OutputEvent {
+                        key: stroke.key(),
+                        state: KeyState::Released,
+                    });
+                }
+            }
+        }
+        outputs
+    }
+
+    /// Sticky keys press their modifiers while held, like the modifiers themselves.
+    fn sticky(&mut self, layers: &[usize], event: KeyEvent) -> Option<Status> {
+        if event.state.released() {
+            return None;
+        }
+        let sticky = self.lookup(layers, event.key)?.sticky()?.clone();
+        let mut pressed = Vec::new();
+        // a generic key would be released on both sides by other outputs
+        for key in sticky.modifiers.keys().map(VirtualKey::left) {
+            if self.has_key(key) {
+                continue;
+            }
+            self.press_key(key);
+            pressed.push(key);
+        }
+        let outputs = pressed
+            .iter()
+            .map(|key| OutputEvent {
+                key: *key,
+                state: KeyState::Pressed,
+            })
+            .collect();
+        self.consumed.push((
+            event.key,
+            Consumed::Sticky {
+                sticky,
+                pressed,
+                used: false,
+            },
+        ));
+        Some(Status::Replace(outputs))
+    }
+
+    /// A tap without other keys latches the modifiers, a second tap locks them until the third.
+    fn latch(&mut self, key: VirtualKey, sticky: Sticky, time: u32) {
+        match self.latches.iter().position(|latch| latch.key == key) {
+            Some(index) if self.latches[index].locked => {
+                self.latches.remove(index);
+            }
+            Some(index) => self.latches[index].locked = true,
+            None => self.latches.push(Latch::new(key, sticky, time)),
+        }
+    }
+
+    /// Hold the latched modifiers for the press of the next key, returns the newly pressed ones.
+    fn press_latched(&mut self, event: KeyEvent) -> Vec<VirtualKey> {
+        if event.state.released() || event.key.is_modifier() || self.has_key(event.key) {
+            return Vec::new();
+        }
+        let modifiers = self
+            .latches
+            .iter()
+            .flat_map(|latch| latch.sticky.modifiers.keys())
+            .collect::<Vec<_>>();
+        self.latches.retain(|latch| latch.locked);
+
+        let mut pressed = Vec::new();
+        for key in modifiers {
+            if self.has_key(key) || pressed.contains(&key) {
+                continue;
+            }
+            self.press_key(key);
+            pressed.push(key);
+        }
+        pressed
+    }
+
+    /// Wrap the status in the presses and releases of the latched modifiers.
+    fn release_latched(
+        &mut self,
+        latched: Vec<VirtualKey>,
+        status: Status,
+        event: KeyEvent,
+    ) -> Status {
+        if latched.is_empty() {
+            return status;
+        }
+        let mut pressed = Vec::new();
+        let mut released = Vec::new();
+        for key in latched {
+            self.release_key(key);
+            pressed.push(OutputEvent {
+                key,
+                state: KeyState::Pressed,
+            });
+            released.push(OutputEvent {
+                key,
+                state: KeyState::Released,
+            });
+        }
+        released.reverse();
+
+        let mut steps = vec![MacroStep::Send(pressed)];
+        append_steps(&mut steps, status.into_steps(event));
+        append_steps(&mut steps, vec![MacroStep::Send(released)]);
+        Status::from_steps(steps)
+    }
+
+    fn switch_profile(&mut self, event: KeyEvent) -> Option<Status> {
+        if event.state.released() {
+            return None;
+        }
+
+        let target = self.profiles.switch_target(&self.keyboard, event.key)?;
+        self.profiles.select(target);
+        self.layers.clear();
+        self.reset_sequences();
+        self.consumed.push((event.key, Consumed::Intercept));
+        Some(Status::Intercept)
+    }
+
+    /// Mappings which are not triggered by the press, `None` for the others.
+    fn trigger(
+        &mut self,
+        layers: &[usize],
+        event: KeyEvent,
+        latched: &[VirtualKey],
+    ) -> Option<Status> {
+        if event.state.released() || self.has_key(event.key) {
+            return None;
+        }
+        self.taps.retain(|(key, _)| *key == event.key);
+
+        let mapping = self
+            .lookup(layers, event.key)
+            .filter(|mapping| mapping.trigger() != Trigger::Press)?
+            .clone();
+        match mapping.trigger() {
+            Trigger::Press => None,
+            Trigger::Release => {
+                let status = match event.key.is_modifier() {
+                    true => Status::Allow,
+                    false => Status::Intercept,
+                };
+                self.consumed
+                    .push((event.key, Consumed::Release(Box::new(mapping))));
+                Some(status)
+            }
+            Trigger::DoubleTap => {
+                let term = mapping.double_tap_term();
+                let second = self
+                    .taps
+                    .iter()
+                    .any(|(key, time)| *key == event.key && event.time.wrapping_sub(*time) < term);
+                self.taps.clear();
+                if second.not() {
+                    self.taps.push((event.key, event.time));
+                    return Some(Status::Allow);
+                }
+                let (steps, stroke) = self.press(&mapping, latched);
+                self.consumed.push((event.key, Consumed::Output(stroke)));
+                Some(Status::from_steps(steps))
+            }
+        }
+    }
+
+    fn remap(&mut self, layers: &[usize], event: KeyEvent, latched: &[VirtualKey]) -> Status {
+        // keys without a mapping at the press are never remapped until their release
+        if event.state.released() || self.has_key(event.key) {
+            return Status::Allow;
+        }
+        let Some(mapping) = self.lookup(layers, event.key) else {
+            return Status::Allow;
+        };
+        let (steps, stroke) = self.press(self.chain(layers, mapping), latched);
+        self.consumed.push((event.key, Consumed::Output(stroke)));
+        Status::from_steps(steps)
+    }
+
+    /// Outputs for pressing the input of the mapping and the stroke held until its release,
+    /// macros and text are played once per press.
+    fn press(&self, mapping: &Mapping, latched: &[VirtualKey]) -> (Vec<MacroStep>, Option<Stroke>) {
+        let steps = match mapping.text() {
+            Some(text) => vec![Step::Text(text.to_owned())],
+            None => mapping.steps().to_vec(),
+        };
+        if steps.is_empty().not() {
+            return (self.create_macro(&steps), None);
+        }
+        let Some(stroke) = self.output_stroke(mapping, latched) else {
+            return (Vec::new(), None);
+        };
+        let outputs = self.press_outputs(&stroke);
+        // the latched modifiers are released right after the press
+        let keyboard = stroke
+            .keyboard()
+            .iter()
+            .copied()
+            .filter(|key| latched.contains(key).not())
+            .collect();
+        (
+            vec![MacroStep::Send(outputs)],
+            Some(Stroke::new(keyboard, stroke.key())),
+        )
+    }
+
+    /// Outputs for releasing the stroke held by [`RemapEngine::press`].
+    fn release(&self, stroke: Option<&Stroke>) -> Vec<MacroStep> {
+        match stroke {
+            Some(stroke) => vec![MacroStep::Send(self.release_outputs(stroke))],
+            None => Vec::new(),
+        }
+    }
+
+    /// Press and release the mapping at once.
+    fn tap(&self, mapping: &Mapping) -> Vec<MacroStep> {
+        let (mut steps, stroke) = self.press(mapping, &[]);
+        append_steps(&mut steps, self.release(stroke.as_ref()));
+        steps
+    }
+
+    /// Output of the mapping, with the held modifiers outside of the input if they are kept
+    /// and the latched modifiers of sticky keys.
+    fn output_stroke(&self, mapping: &Mapping, latched: &[VirtualKey]) -> Option<Stroke> {
+        let output = mapping.get(Side::Output)?;
+        let held = match mapping.keep_modifiers() {
+            true => self.keyboard.as_slice(),
+            false => latched,
+        };
+        let input = mapping
+            .get(Side::Input)
+            .map(Stroke::keyboard)
+            .unwrap_or_default();
+        let mut keyboard = output.keyboard().to_vec();
+        for key in held.iter().copied() {
+            if key.is_modifier().not()
+                || keyboard.contains(&key)
+                || input.iter().any(|input| input.generic() == key.generic())
+            {
+                continue;
+            }
+            keyboard.push(key);
+        }
+        Some(Stroke::new(keyboard, output.key()))
+    }
+
+    /// Track the event without remapping, returns the stroke of all keys pressed together
+    /// once every key is released.
+    pub fn capture(&mut self, event: KeyEvent) -> Option<Stroke> {
+        if event.state.pressed() {
+            if self.keyboard.is_empty() {
+                self.chord.clear();
+            }
+            if self.chord.contains(&event.key).not() {
+                self.chord.push(event.key);
+            }
+            self.press_key(event.key);
+            return None;
+        }
+
+        self.release_key(event.key);
+        self.presses.retain(|(key, _)| *key != event.key);
+        if self.keyboard.is_empty().not() {
+            return None;
+        }
+        let stroke = Stroke::from_chord(&self.chord);
+        self.chord.clear();
+        stroke
+    }
+
+    /// Follow the outputs of chained mappings through the mapping table, up to the chain depth.
+    fn chain<'a>(&'a self, layers: &[usize], mut mapping: &'a Mapping) -> &'a Mapping {
+        let mut visited = vec![mapping];
+        while mapping.chain() && mapping.text().is_none() && mapping.steps().is_empty() {
+            let Some(output) = mapping.get(Side::Output) else {
+                break;
+            };
+            let Some(next) = self.lookup_stroke(layers, output.keyboard(), output.key()) else {
+                break;
+            };
+            if visited.iter().any(|other| std::ptr::eq(*other, next)) {
+                println!("Chained mappings loop at {}", output);
+                break;
+            }
+            if visited.len() > self.profiles.chain_depth() as usize {
+                println!(
+                    "Chained mappings deeper than {} at {}",
+                    self.profiles.chain_depth(),
+                    output
+                );
+                break;
+            }
+            visited.push(next);
+            mapping = next;
+        }
+        mapping
+    }
+
+    fn lookup(&self, layers: &[usize], key: VirtualKey) -> Option<&Mapping> {
+        self.lookup_stroke(layers, &self.keyboard, key)
+    }
+
+    /// Search the layers top-down and then the profile, transparent mappings fall through.
+    fn lookup_stroke(
+        &self,
+        layers: &[usize],
+        keyboard: &[VirtualKey],
+        key: VirtualKey,
+    ) -> Option<&Mapping> {
+        // only queried if a scoped mapping would match
+        let window = OnceCell::new();
+        let profile = self.profiles.active();
+        layers
+            .iter()
+            .filter_map(|index| profile.layers.get(*index))
+            .map(|layer| layer.mappings.as_slice())
+            .chain([profile.mappings.as_slice()])
+            .find_map(|mappings| {
+                self.find_mapping(mappings, keyboard, key, &window)
+                    .filter(|mapping| mapping.transparent().not())
+            })
+    }
+
+    fn find_mapping<'a>(
+        &self,
+        mappings: &'a [Mapping],
+        keyboard: &[VirtualKey],
+        key: VirtualKey,
+        window: &OnceCell<Option<WindowInfo>>,
+    ) -> Option<&'a Mapping> {
+        keys::resolve(mappings, keyboard, key, |mapping| {
+            self.in_scope(mapping, window)
+        })
+        .map(|(_, mapping)| mapping)
+    }
+
+    /// Scoped mappings only apply while a matching window is in the foreground.
+    fn in_scope(&self, mapping: &Mapping, window: &OnceCell<Option<WindowInfo>>) -> bool {
+        let Some(scope) = mapping.scope() else {
+            return true;
+        };
+        window
+            .get_or_init(|| self.windows.foreground())
+            .as_ref()
+            .is_some_and(|window| scope.matches(window))
+    }
+
+    /// Release the held keys which are not part of the stroke and press the stroke,
+    /// the held keys stay released until [`RemapEngine::release_outputs`].
+    fn press_outputs(&self, stroke: &Stroke) -> Vec<OutputEvent> {
+        let mut outputs = Vec::new();
+        let mut push = |key, state| outputs.push(OutputEvent { key, state });
+        for key in self
+            .keyboard
+            .iter()
+            .copied()
+            .filter(|key| stroke.keyboard().contains(key).not())
+        {
+            push_sides(&mut push, key, KeyState::Released);
+        }
+        for key in stroke
+            .keyboard()
+            .iter()
+            .copied()
+            .filter(|key| self.keyboard.contains(key).not())
+        {
+            push(key, KeyState::Pressed);
+        }
+        push(stroke.key(), KeyState::Pressed);
+        outputs
+    }
+
+    /// Release the stroke and press the keys which are still held again.
+    fn release_outputs(&self, stroke: &Stroke) -> Vec<OutputEvent> {
+        let mut outputs = Vec::new();
+        let mut push = |key, state| outputs.push(OutputEvent { key, state });
+        push(stroke.key(), KeyState::Released);
+        for key in stroke
+            .keyboard()
+            .iter()
+            .rev()
+            .copied()
+            .filter(|key| self.keyboard.contains(key).not())
+        {
+            push(key, KeyState::Released);
+        }
+        for key in self
+            .keyboard
+            .iter()
+            .rev()
+            .copied()
+            .filter(|key| stroke.keyboard().contains(key).not())
+        {
+            push_sides(&mut push, key, KeyState::Pressed);
+        }
+        outputs
+    }
+
+    fn create_outputs(&self, stroke: &Stroke, state: KeyState) -> Vec<OutputEvent> {
+        let mut outputs = Vec::new();
+        let mut push = |key, state| outputs.push(OutputEvent { key, state });
+
+        // release
+        for key in self
+            .keyboard
+            .iter()
+            .copied()
+            .filter(|key| stroke.keyboard().contains(key).not())
+        {
+            push_sides(&mut push, key, KeyState::Released);
+        }
+
+        // press
+        for key in stroke
+            .keyboard()
+            .iter()
+            .copied()
+            .filter(|key| self.keyboard.contains(key).not())
+        {
+            push(key, KeyState::Pressed);
+        }
+
+        push(stroke.key(), state);
+
+        for idx in (0..(outputs.len() - 1)).rev() {
+            let output = outputs[idx];
+            outputs.push(OutputEvent {
+                key: output.key,
+                state: output.state.opposite(),
+            });
+        }
+
+        outputs
+    }
+
+    /// Release the held keys once and play the steps without restoring them,
+    /// the held keys may be released during the waits.
+    fn create_macro(&self, steps: &[Step]) -> Vec<MacroStep> {
+        let mut macro_steps = Vec::new();
+        let mut outputs = Vec::new();
+        for key in self.keyboard.iter().copied() {
+            push_sides(
+                &mut |key, state| outputs.push(OutputEvent { key, state }),
+                key,
+                KeyState::Released,
+            );
+        }
+
+        for step in steps {
+            let (pressed, released) = match step {
+                Step::Stroke(stroke) => (Some(stroke), Some(stroke)),
+                Step::Down(stroke) => (Some(stroke), None),
+                Step::Up(stroke) => (None, Some(stroke)),
+                Step::Text(text) => {
+                    macro_steps.push(MacroStep::Send(std::mem::take(&mut outputs)));
+                    macro_steps.push(MacroStep::Text(text.clone()));
+                    continue;
+                }
+                Step::Wait(milliseconds) => {
+                    macro_steps.push(MacroStep::Send(std::mem::take(&mut outputs)));
+                    macro_steps.push(MacroStep::Wait(*milliseconds));
+                    continue;
+                }
+            };
+            for key in pressed.into_iter().flat_map(Stroke::keys) {
+                outputs.push(OutputEvent {
+                    key,
+                    state: KeyState::Pressed,
+                });
+            }
+            for key in released.into_iter().flat_map(|stroke| stroke.keys().rev()) {
+                outputs.push(OutputEvent {
+                    key,
+                    state: KeyState::Released,
+                });
+            }
+        }
+        macro_steps.push(MacroStep::Send(outputs));
+        macro_steps.retain(|step| step != &MacroStep::Send(Vec::new()));
+        macro_steps
+    }
+
+    fn press_key(&mut self, key: VirtualKey) {
+        match key {
+            VK_LSHIFT if self.has_key(VK_RSHIFT) => self.replace_key(VK_RSHIFT, VK_SHIFT),
+            VK_RSHIFT if self.has_key(VK_LSHIFT) => self.replace_key(VK_LSHIFT, VK_SHIFT),
+            VK_LSHIFT | VK_RSHIFT if self.has_key(VK_SHIFT) => {}
+
+            VK_LCONTROL if self.has_key(VK_RCONTROL) => self.replace_key(VK_RCONTROL, VK_CONTROL),
+            VK_RCONTROL if self.has_key(VK_LCONTROL) => self.replace_key(VK_LCONTROL, VK_CONTROL),
+            VK_LCONTROL | VK_RCONTROL if self.has_key(VK_CONTROL) => {}
+
+            VK_LMENU if self.has_key(VK_RMENU) => self.replace_key(VK_RMENU, VK_MENU),
+            VK_RMENU if self.has_key(VK_LMENU) => self.replace_key(VK_LMENU, VK_MENU),
+            VK_LMENU | VK_RMENU if self.has_key(VK_MENU) => {}
+
+            _ if self.keyboard.contains(&key).not() => self.keyboard.push(key),
+            _ => {}
+        }
+    }
+
+    fn has_key(&self, key: VirtualKey) -> bool {
+        self.keyboard.contains(&key)
+    }
+
+    fn replace_key(&mut self, old: VirtualKey, new: VirtualKey) {
+        let Some(v) = self.keyboard.iter_mut().find(|key| **key == old) else {
+            return;
+        };
+        *v = new;
+    }
+
+    fn release_key(&mut self, key: VirtualKey) {
+        match key {
+            VK_LSHIFT if self.has_key(VK_SHIFT) => self.replace_key(VK_SHIFT, VK_RSHIFT),
+            VK_RSHIFT if self.has_key(VK_SHIFT) => self.replace_key(VK_SHIFT, VK_LSHIFT),
+
+            VK_LCONTROL if self.has_key(VK_CONTROL) => self.replace_key(VK_CONTROL, VK_RCONTROL),
+            VK_RCONTROL if self.has_key(VK_CONTROL) => self.replace_key(VK_CONTROL, VK_LCONTROL),
+
+            VK_LMENU if self.has_key(VK_MENU) => self.replace_key(VK_MENU, VK_RMENU),
+            VK_RMENU if self.has_key(VK_MENU) => self.replace_key(VK_MENU, VK_LMENU),
+            _ => {
+                self.keyboard.retain_mut(|v| *v != key);
+            }
+        }
+    }
+}
+
+/// Generic modifiers change both sides.
+fn push_sides(push: &mut impl FnMut(VirtualKey, KeyState), key: VirtualKey, state: KeyState) {
+    match key {
+        VK_SHIFT => {
+            push(VK_LSHIFT, state);
+            push(VK_RSHIFT, state);
+        }
+        VK_CONTROL => {
+            push(VK_LCONTROL, state);
+            push(VK_RCONTROL, state);
+        }
+        VK_MENU => {
+            push(VK_LMENU, state);
+            push(VK_RMENU, state);
+        }
+        _ => push(key, state),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{keys::MappingData, profile::Profile, sequence::SEQUENCE_TIMEOUT};
+
+    fn engine(json: &str) -> RemapEngine {
+        let mappings = serde_json::from_str::<Vec<MappingData>>(json)
+            .unwrap()
+            .into_iter()
+            .map(Mapping::from)
+            .collect();
+        let profile = Profile::new("Default".to_owned(), mappings);
+        RemapEngine::new(Profiles::new(vec![profile], None))
+    }
+
+    fn key(name: &str) -> VirtualKey {
+        VirtualKey::from_name(name).unwrap()
+    }
+
+    fn event(name: &str, state: KeyState, time: u32) -> KeyEvent {
+        KeyEvent {
+            key: key(name),
+            state,
+            injected: false,
+            time,
+        }
+    }
+
+    fn replace(outputs: &[(&str, KeyState)]) -> Status {
+        Status::Replace(
+            outputs
+                .iter()
+                .map(|(name, state)| OutputEvent {
+                    key: key(name),
+                    state: *state,
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn plain_remap() {
+        let mut engine = engine(r#"[{ "input": "A", "output": "B" }]"#);
+        let status = engine.handle(event("A", KeyState::Pressed, 0));
+        assert_eq!(status, replace(&[("B", KeyState::Pressed)]));
+        let status = engine.handle(event("A", KeyState::Released, 10));
+        assert_eq!(status, replace(&[("B", KeyState::Released)]));
+        let status = engine.handle(event("C", KeyState::Pressed, 20));
+        assert_eq!(status, Status::Allow);
+        let status = engine.handle(event("C", KeyState::Released, 30));
+        assert_eq!(status, Status::Allow);
+    }
+
+    #[derive(Debug)]
+    struct Window(&'static str);
+
+    impl WindowProvider for Window {
+        fn foreground(&self) -> Option<WindowInfo> {
+            Some(WindowInfo {
+                process: self.0.to_owned(),
+                title: String::new(),
+            })
+        }
+    }
+
+    #[test]
+    fn scoped_mappings() {
+        let mut engine = engine(
+            r#"[
+                { "input": "A", "output": "B", "scope": { "process": "code.exe" } },
+                { "input": "A", "output": "C" }
+            ]"#,
+        );
+        let status = engine.handle(event("A", KeyState::Pressed, 0));
+        assert_eq!(status, replace(&[("C", KeyState::Pressed)]));
+        engine.handle(event("A", KeyState::Released, 10));
+
+        engine.set_window_provider(Box::new(Window("Code.exe")));
+        let status = engine.handle(event("A", KeyState::Pressed, 20));
+        assert_eq!(status, replace(&[("B", KeyState::Pressed)]));
+    }
+
+    #[test]
+    fn tap_and_hold_at_the_tapping_term() {
+        let mut engine = engine(
+            r#"[{ "input": "CapsLock", "output": "Esc", "hold": "LControl", "tapping_term": 200 }]"#,
+        );
+        assert_eq!(
+            engine.handle(event("CapsLock", KeyState::Pressed, 0)),
+            Status::Intercept
+        );
+        let status = engine.handle(event("CapsLock", KeyState::Released, 199));
+        assert_eq!(
+            status,
+            replace(&[("Esc", KeyState::Pressed), ("Esc", KeyState::Released)])
+        );
+
+        engine.handle(event("CapsLock", KeyState::Pressed, 1000));
+        let status = engine.handle(event("CapsLock", KeyState::Pressed, 1199));
+        assert_eq!(status, Status::Intercept);
+        let status = engine.handle(event("CapsLock", KeyState::Pressed, 1200));
+        assert_eq!(status, replace(&[("LControl", KeyState::Pressed)]));
+        let status = engine.handle(event("CapsLock", KeyState::Released, 1300));
+        assert_eq!(status, replace(&[("LControl", KeyState::Released)]));
+        let status = engine.handle(event("A", KeyState::Pressed, 1400));
+        assert_eq!(status, Status::Allow);
+    }
+
+    const STICKY_SHIFT: &str = r#"[{ "input": "LShift", "sticky": "Shift" }]"#;
+
+    fn tap(engine: &mut RemapEngine, name: &str, time: u32) {
+        engine.handle(event(name, KeyState::Pressed, time));
+        engine.handle(event(name, KeyState::Released, time + 10));
+    }
+
+    #[test]
+    fn sticky_key_is_a_modifier_while_held() {
+        let mut engine = engine(STICKY_SHIFT);
+        let status = engine.handle(event("LShift", KeyState::Pressed, 0));
+        assert_eq!(status, replace(&[("LShift", KeyState::Pressed)]));
+        let status = engine.handle(event("LShift", KeyState::Pressed, 30));
+        assert_eq!(status, Status::Intercept);
+        for time in [40, 70, 100] {
+            let status = engine.handle(event("A", KeyState::Pressed, time));
+            assert_eq!(status, Status::Allow);
+        }
+        engine.handle(event("A", KeyState::Released, 110));
+        let status = engine.handle(event("LShift", KeyState::Released, 120));
+        assert_eq!(status, replace(&[("LShift", KeyState::Released)]));
+
+        // used while held, nothing is latched
+        let status = engine.handle(event("B", KeyState::Pressed, 130));
+        assert_eq!(status, Status::Allow);
+    }
+
+    #[test]
+    fn sticky_tap_latches_the_next_key() {
+        let mut engine = engine(STICKY_SHIFT);
+        tap(&mut engine, "LShift", 0);
+        let status = engine.handle(event("A", KeyState::Pressed, 100));
+        let shifted = replace(&[
+            ("Shift", KeyState::Pressed),
+            ("A", KeyState::Pressed),
+            ("Shift", KeyState::Released),
+        ]);
+        assert_eq!(status, shifted);
+        engine.handle(event("A", KeyState::Released, 110));
+        let status = engine.handle(event("B", KeyState::Pressed, 120));
+        assert_eq!(status, Status::Allow);
+    }
+
+    #[test]
+    fn sticky_double_tap_locks() {
+        let mut engine = engine(STICKY_SHIFT);
+        tap(&mut engine, "LShift", 0);
+        tap(&mut engine, "LShift", 100);
+        for (name, time) in [("A", 200), ("B", 5000)] {
+            let status = engine.handle(event(name, KeyState::Pressed, time));
+            let shifted = replace(&[
+                ("Shift", KeyState::Pressed),
+                (name, KeyState::Pressed),
+                ("Shift", KeyState::Released),
+            ]);
+            assert_eq!(status, shifted);
+            engine.handle(event(name, KeyState::Released, time + 10));
+        }
+        tap(&mut engine, "LShift", 6000);
+        let status = engine.handle(event("A", KeyState::Pressed, 6100));
+        assert_eq!(status, Status::Allow);
+    }
+
+    #[test]
+    fn sticky_latch_expires() {
+        let mut engine = engine(STICKY_SHIFT);
+        tap(&mut engine, "LShift", 0);
+        let status = engine.handle(event("A", KeyState::Pressed, 1010));
+        assert_eq!(status, Status::Allow);
+    }
+
+    #[test]
+    fn tick_decides_a_hold_without_events() {
+        let mut engine = engine(
+            r#"[{ "input": "CapsLock", "output": "Esc", "hold": "LControl", "tapping_term": 200 }]"#,
+        );
+        engine.handle(event("CapsLock", KeyState::Pressed, 0));
+        assert_eq!(engine.tick(199), Vec::new());
+        let pressed = OutputEvent {
+            key: key("LControl"),
+            state: KeyState::Pressed,
+        };
+        assert_eq!(engine.tick(200), vec![MacroStep::Send(vec![pressed])]);
+        let status = engine.handle(event("CapsLock", KeyState::Pressed, 230));
+        assert_eq!(status, Status::Intercept);
+        let status = engine.handle(event("CapsLock", KeyState::Released, 300));
+        assert_eq!(status, replace(&[("LControl", KeyState::Released)]));
+    }
+
+    #[test]
+    fn failed_combo_replays_its_keys() {
+        let mut engine = engine(r#"[{ "combo": ["J", "K"], "output": "Esc" }]"#);
+        let status = engine.handle(event("J", KeyState::Pressed, 0));
+        assert_eq!(status, Status::Intercept);
+        let status = engine.handle(event("L", KeyState::Pressed, 10));
+        let replayed = replace(&[("J", KeyState::Pressed), ("L", KeyState::Pressed)]);
+        assert_eq!(status, replayed);
+        engine.handle(event("L", KeyState::Released, 20));
+        engine.handle(event("J", KeyState::Released, 30));
+
+        engine.handle(event("J", KeyState::Pressed, 100));
+        assert_eq!(engine.tick(130), Vec::new());
+        let pressed = OutputEvent {
+            key: key("J"),
+            state: KeyState::Pressed,
+        };
+        assert_eq!(engine.tick(131), vec![MacroStep::Send(vec![pressed])]);
+        let status = engine.handle(event("J", KeyState::Released, 150));
+        assert_eq!(status, Status::Allow);
+    }
+
+    #[test]
+    fn sequences_replay_their_strokes() {
+        let mut engine = engine(r#"[{ "input": ["G", "G"], "output": "Esc" }]"#);
+        assert_eq!(
+            engine.handle(event("G", KeyState::Pressed, 0)),
+            Status::Intercept
+        );
+        assert_eq!(
+            engine.handle(event("G", KeyState::Released, 10)),
+            Status::Intercept
+        );
+        let status = engine.handle(event("G", KeyState::Pressed, 20));
+        assert_eq!(
+            status,
+            replace(&[("Esc", KeyState::Pressed), ("Esc", KeyState::Released)])
+        );
+        assert_eq!(
+            engine.handle(event("G", KeyState::Released, 30)),
+            Status::Intercept
+        );
+
+        // another key sends the strokes typed so far
+        engine.handle(event("G", KeyState::Pressed, 100));
+        engine.handle(event("G", KeyState::Released, 110));
+        let status = engine.handle(event("H", KeyState::Pressed, 120));
+        let replayed = replace(&[
+            ("G", KeyState::Pressed),
+            ("G", KeyState::Released),
+            ("H", KeyState::Pressed),
+        ]);
+        assert_eq!(status, replayed);
+        assert_eq!(
+            engine.handle(event("H", KeyState::Released, 130)),
+            Status::Allow
+        );
+
+        // and so does the timeout
+        engine.handle(event("G", KeyState::Pressed, 200));
+        assert_eq!(engine.tick(200 + SEQUENCE_TIMEOUT - 1), Vec::new());
+        let pressed = OutputEvent {
+            key: key("G"),
+            state: KeyState::Pressed,
+        };
+        assert_eq!(
+            engine.tick(200 + SEQUENCE_TIMEOUT),
+            vec![MacroStep::Send(vec![pressed])]
+        );
+        let status = engine.handle(event("G", KeyState::Released, 1300));
+        assert_eq!(status, Status::Allow);
+    }
+
+    #[test]
+    fn interrupted_release_trigger_replays_its_press() {
+        let mut engine = engine(r#"[{ "input": "Space", "output": "F13", "trigger": "release" }]"#);
+        let status = engine.handle(event("Space", KeyState::Pressed, 0));
+        assert_eq!(status, Status::Intercept);
+        let status = engine.handle(event("Space", KeyState::Released, 10));
+        assert_eq!(
+            status,
+            replace(&[("F13", KeyState::Pressed), ("F13", KeyState::Released)])
+        );
+
+        engine.handle(event("Space", KeyState::Pressed, 100));
+        let status = engine.handle(event("X", KeyState::Pressed, 110));
+        assert_eq!(
+            status,
+            replace(&[("Space", KeyState::Pressed), ("X", KeyState::Pressed)])
+        );
+        let status = engine.handle(event("X", KeyState::Released, 120));
+        assert_eq!(status, Status::Allow);
+        let status = engine.handle(event("Space", KeyState::Released, 130));
+        assert_eq!(status, Status::Allow);
+    }
+
+    #[test]
+    fn released_alt_is_masked() {
+        let mut engine = engine(r#"[{ "input": "LMenu", "output": "F13", "trigger": "release" }]"#);
+        let status = engine.handle(event("LMenu", KeyState::Pressed, 0));
+        assert_eq!(status, Status::Allow);
+        let status = engine.handle(event("LMenu", KeyState::Released, 10));
+        let masked = [
+            (VK_MASK, KeyState::Pressed),
+            (VK_MASK, KeyState::Released),
+            (key("LMenu"), KeyState::Released),
+            (key("F13"), KeyState::Pressed),
+            (key("F13"), KeyState::Released),
+        ];
+        let outputs = masked
+            .into_iter()
+            .map(|(key, state)| OutputEvent { key, state })
+            .collect();
+        assert_eq!(status, Status::Replace(outputs));
+
+        // Alt+Tab works as usual
+        engine.handle(event("LMenu", KeyState::Pressed, 100));
+        let status = engine.handle(event("Tab", KeyState::Pressed, 110));
+        assert_eq!(status, Status::Allow);
+        engine.handle(event("Tab", KeyState::Released, 120));
+        let status = engine.handle(event("LMenu", KeyState::Released, 130));
+        assert_eq!(status, Status::Allow);
+    }
+}
@@ -1,19 +1,23 @@
-use std::ops::Not;
+use std::{fmt, ops::Not};
 
 use gpui::*;
 use prelude::FluentBuilder;
-use windows::Win32::UI::Input::KeyboardAndMouse::*;
+use serde::de;
 
-use crate::{ui::Interactivity, vk_table::VirtualKeyExtension};
+use crate::{
+    layer::LayerAction,
+    macros::{Step, StepData},
+    scope::Scope,
+    ui::Interactivity,
+    vk_table::*,
+};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Stroke {
-    keyboard: Vec<VIRTUAL_KEY>,
-    key: VIRTUAL_KEY,
+    keyboard: Vec<VirtualKey>,
+    key: VirtualKey,
 }
 
-pub const SET_BIT: u8 = 0x80;
-
 impl Stroke {
     pub fn render(&self, interactivity: Interactivity) -> impl IntoElement {
         let mut modifier = false;
@@ -29,7 +33,7 @@ impl Stroke {
                 self.keyboard
                     .iter()
                     .copied()
-                    .map(|key| render_key(key.name().into(), interactivity))
+                    .map(|key| render_key(key.to_string(), interactivity))
                     .inspect(|_| modifier = true),
             )
             .when(modifier, |div| {
@@ -41,39 +45,186 @@ impl Stroke {
                         .text_color(interactivity.foreground()),
                 )
             })
-            .child(render_key(self.key.name().into(), interactivity))
+            .child(render_key(self.key.to_string(), interactivity))
     }
 }
 
 impl Stroke {
-    pub fn new(keyboard: Vec<VIRTUAL_KEY>, key: VIRTUAL_KEY) -> Self {
+    pub fn new(keyboard: Vec<VirtualKey>, key: VirtualKey) -> Self {
         Self { keyboard, key }
     }
 
-    pub fn key(&self) -> VIRTUAL_KEY {
+    /// Stroke of keys pressed together, the last key which is not a modifier is pressed last.
+    pub fn from_chord(keys: &[VirtualKey]) -> Option<Self> {
+        let index = keys
+            .iter()
+            .rposition(|key| key.is_modifier().not())
+            .or(keys.len().checked_sub(1))?;
+        let mut keyboard = keys.to_vec();
+        let key = keyboard.remove(index);
+        Some(Self::new(keyboard, key))
+    }
+
+    pub fn key(&self) -> VirtualKey {
         self.key
     }
 
-    pub fn keyboard(&self) -> &[VIRTUAL_KEY] {
+    pub fn keyboard(&self) -> &[VirtualKey] {
         &self.keyboard
     }
+
+    /// Pressing `key` while holding `keyboard` triggers the stroke.
+    /// Modifiers followed by the key.
+    pub fn keys(&self) -> impl DoubleEndedIterator<Item = VirtualKey> + '_ {
+        self.keyboard.iter().copied().chain([self.key])
+    }
+
+    /// Only modifiers, like `Control+Shift`.
+    pub fn is_modifier_only(&self) -> bool {
+        self.keys().all(VirtualKey::is_modifier)
+    }
+
+    pub fn matches(&self, keyboard: &[VirtualKey], key: VirtualKey) -> bool {
+        // modifiers pressed in any order, the last one triggers
+        if self.is_modifier_only() {
+            return self.keys().any(|other| same_key(other, key))
+                && self
+                    .keys()
+                    .all(|other| same_key(other, key) || is_held(keyboard, other));
+        }
+        if same_key(self.key, key).not() {
+            return false;
+        }
+
+        self.keyboard.iter().all(|key| is_held(keyboard, *key))
+    }
+
+    /// More modifiers and then more side-specific modifiers are more specific.
+    pub fn specificity(&self) -> (usize, usize) {
+        let sided = self
+            .keyboard
+            .iter()
+            .filter(|key| key.generic() != **key)
+            .count();
+        (self.keyboard.len(), sided)
+    }
+
+    /// Like [`Stroke::matches`], but no other modifiers may be held.
+    pub fn matches_exactly(&self, keyboard: &[VirtualKey], key: VirtualKey) -> bool {
+        self.matches(keyboard, key)
+            && keyboard
+                .iter()
+                .copied()
+                .filter(|held| held.is_modifier())
+                .all(|held| {
+                    self.keys().any(|modifier| {
+                        modifier == held || modifier == held.generic() || modifier.generic() == held
+                    })
+                })
+    }
+}
+
+/// The pressed key is `key` or one of its sides.
+fn same_key(key: VirtualKey, pressed: VirtualKey) -> bool {
+    key == pressed || key == pressed.generic()
+}
+
+/// A generic modifier is held with either side.
+fn is_held(keyboard: &[VirtualKey], key: VirtualKey) -> bool {
+    keyboard.iter().any(|held| same_key(key, *held))
+}
+
+impl fmt::Display for Stroke {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for key in self.keyboard.iter() {
+            write!(f, "{}+", key)?;
+        }
+        write!(f, "{}", self.key)
+    }
 }
 
-#[derive(Debug, serde::Serialize, serde::Deserialize)]
+/// Stroke written as key names, for example `"Control+Shift+A"`.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(try_from = "String", into = "String")]
 pub struct StrokeData {
-    key: u16,
-    keyboard: Vec<u8>,
+    key: VirtualKey,
+    keyboard: Vec<VirtualKey>,
+}
+
+impl TryFrom<String> for StrokeData {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Self, Self::Error> {
+        let mut keys = text
+            .split('+')
+            .map(|name| {
+                let name = name.trim();
+                VirtualKey::from_name(name).ok_or_else(|| format!("Unknown key '{}'", name))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let key = keys.pop().ok_or_else(|| String::from("Empty stroke"))?;
+        Ok(Self {
+            key,
+            keyboard: keys,
+        })
+    }
+}
+
+impl From<StrokeData> for String {
+    fn from(stroke_data: StrokeData) -> Self {
+        Stroke::from(stroke_data).to_string()
+    }
+}
+
+/// Input of a mapping, a single stroke or a sequence like `["Control+X", "Control+S"]`.
+pub struct InputData(Vec<StrokeData>);
+
+impl serde::Serialize for InputData {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.0.as_slice() {
+            [stroke] => stroke.serialize(serializer),
+            strokes => strokes.serialize(serializer),
+        }
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for InputData {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(InputVisitor)
+    }
+}
+
+struct InputVisitor;
+
+impl<'de> de::Visitor<'de> for InputVisitor {
+    type Value = InputData;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a stroke or a list of strokes")
+    }
+
+    fn visit_str<E: de::Error>(self, text: &str) -> Result<Self::Value, E> {
+        let stroke = StrokeData::try_from(text.to_owned()).map_err(E::custom)?;
+        Ok(InputData(vec![stroke]))
+    }
+
+    fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut strokes = Vec::new();
+        while let Some(stroke) = seq.next_element()? {
+            strokes.push(stroke);
+        }
+        if strokes.is_empty() {
+            return Err(de::Error::custom("Empty sequence"));
+        }
+        Ok(InputData(strokes))
+    }
 }
 
 impl From<StrokeData> for Stroke {
     fn from(stroke_data: StrokeData) -> Self {
         Self {
-            key: VIRTUAL_KEY(stroke_data.key),
-            keyboard: stroke_data
-                .keyboard
-                .into_iter()
-                .map(|v| VIRTUAL_KEY(v as u16))
-                .collect(),
+            key: stroke_data.key,
+            keyboard: stroke_data.keyboard,
         }
     }
 }
@@ -81,8 +232,8 @@ impl From<StrokeData> for Stroke {
 impl From<Stroke> for StrokeData {
     fn from(stroke: Stroke) -> Self {
         Self {
-            key: stroke.key.0,
-            keyboard: stroke.keyboard.into_iter().map(|k| k.0 as u8).collect(),
+            key: stroke.key,
+            keyboard: stroke.keyboard,
         }
     }
 }
@@ -104,72 +255,280 @@ pub enum Side {
     Output,
 }
 
+/// Decides when a dual-role key counts as held.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HoldMode {
+    /// Held after the tapping term, other keys wait for the decision.
+    #[default]
+    Timeout,
+    /// Also held if another key is pressed and released while the key is down.
+    PermissiveHold,
+    /// Also held as soon as another key is pressed.
+    HoldOnOtherKeyPress,
+}
+
+pub const DEFAULT_TAPPING_TERM: u32 = 200;
+
+/// Output of a dual-role key while held, the normal output is used for a tap.
+#[derive(Debug, Clone)]
+pub struct Hold {
+    pub stroke: Stroke,
+    /// Milliseconds until the key counts as held.
+    pub term: u32,
+    pub mode: HoldMode,
+}
+
+impl fmt::Display for Hold {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "hold {}", self.stroke)
+    }
+}
+
+/// How the held modifiers are compared with the modifiers of the input.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Matching {
+    /// Other modifiers may be held as well.
+    #[default]
+    Subset,
+    /// Only the modifiers of the input may be held.
+    Exact,
+}
+
+impl Matching {
+    pub fn toggled(self) -> Self {
+        match self {
+            Self::Subset => Self::Exact,
+            Self::Exact => Self::Subset,
+        }
+    }
+}
+
+impl fmt::Display for Matching {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Subset => write!(f, "subset"),
+            Self::Exact => write!(f, "exact"),
+        }
+    }
+}
+
+/// Event of the input which triggers the mapping.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Trigger {
+    #[default]
+    Press,
+    /// Released without another key pressed in between, modifiers are still sent.
+    Release,
+    /// Pressed a second time within the double tap term, the first tap is sent.
+    DoubleTap,
+}
+
+impl fmt::Display for Trigger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Press => write!(f, "on press"),
+            Self::Release => write!(f, "on release"),
+            Self::DoubleTap => write!(f, "double tap"),
+        }
+    }
+}
+
+pub const DEFAULT_DOUBLE_TAP_TERM: u32 = 300;
+
+pub const DEFAULT_STICKY_TIMEOUT: u32 = 1000;
+
+/// One-shot modifiers, a tap applies them to the next key and a double tap locks them.
+#[derive(Debug, Clone)]
+pub struct Sticky {
+    pub modifiers: Stroke,
+    /// Milliseconds until an unused tap is forgotten.
+    pub timeout: u32,
+}
+
+impl fmt::Display for Sticky {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "sticky {}", self.modifiers)
+    }
+}
+
+pub const DEFAULT_COMBO_TERM: u32 = 30;
+
+/// Keys pressed together as the input of a mapping.
+#[derive(Debug, Clone)]
+pub struct Combo {
+    pub keys: Vec<VirtualKey>,
+    /// Milliseconds between the first and the last key press.
+    pub term: u32,
+}
+
+impl fmt::Display for Combo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names = self
+            .keys
+            .iter()
+            .map(|key| key.to_string())
+            .collect::<Vec<_>>();
+        write!(f, "combo {}", names.join("+"))
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Mapping {
+    /// Strokes of a sequence before the input.
+    prefix: Vec<Stroke>,
     input: Option<Stroke>,
+    matching: Matching,
+    trigger: Trigger,
+    /// Milliseconds between the presses of a double tap.
+    double_tap_term: u32,
+    /// Used instead of the input if present.
+    combo: Option<Combo>,
     output: Option<Stroke>,
+    hold: Option<Hold>,
+    sticky: Option<Sticky>,
+    /// Typed instead of the output.
+    text: Option<String>,
+    /// Played instead of the output if not empty.
+    steps: Vec<Step>,
+    layer: Option<LayerAction>,
+    /// Matching inputs fall through to the layer below.
+    transparent: bool,
+    /// Held modifiers which are not part of the input are kept on the output.
+    keep_modifiers: bool,
+    /// The output is looked up as input of the other mappings.
+    chain: bool,
+    scope: Option<Scope>,
 }
 
 impl Mapping {
     pub fn new_empty() -> Self {
         Self {
+            prefix: Vec::new(),
             input: None,
+            matching: Matching::Subset,
+            trigger: Trigger::Press,
+            double_tap_term: DEFAULT_DOUBLE_TAP_TERM,
+            combo: None,
             output: None,
+            hold: None,
+            sticky: None,
+            text: None,
+            steps: Vec::new(),
+            layer: None,
+            transparent: false,
+            keep_modifiers: false,
+            chain: false,
+            scope: None,
         }
     }
 
-    pub fn clear(&mut self, side: Side) {
-        match side {
-            Side::Input => self.input = None,
-            Side::Output => self.output = None,
+    pub fn prefix(&self) -> &[Stroke] {
+        &self.prefix
+    }
+
+    /// All strokes of a sequence mapping, `None` for a single stroke.
+    pub fn sequence(&self) -> Option<Vec<Stroke>> {
+        if self.prefix.is_empty() {
+            return None;
         }
+        let input = self.input.clone()?;
+        Some(self.prefix.iter().cloned().chain([input]).collect())
     }
 
-    pub fn is_empty(&self) -> bool {
-        self.input.is_none() && self.output.is_none()
+    pub fn matching(&self) -> Matching {
+        self.matching
     }
 
-    pub fn status(&self, keyboard: &[VIRTUAL_KEY], key: VIRTUAL_KEY) -> Option<Option<Stroke>> {
-        let Some(input) = &self.input else {
-            return None;
-        };
+    pub fn set_matching(&mut self, matching: Matching) {
+        self.matching = matching;
+    }
 
-        if input.key != key {
-            return None;
+    /// Inputs of only modifiers trigger on the release, they are part of other strokes as well.
+    pub fn trigger(&self) -> Trigger {
+        match self.trigger {
+            Trigger::Press if self.input.as_ref().is_some_and(Stroke::is_modifier_only) => {
+                Trigger::Release
+            }
+            trigger => trigger,
         }
+    }
 
-        let valid = input.keyboard.iter().copied().all(|key| match key {
-            VK_SHIFT => {
-                keyboard.contains(&VK_SHIFT)
-                    | keyboard.contains(&VK_LSHIFT)
-                    | keyboard.contains(&VK_RSHIFT)
-            }
-            VK_CONTROL => {
-                keyboard.contains(&VK_CONTROL)
-                    | keyboard.contains(&VK_LCONTROL)
-                    | keyboard.contains(&VK_RCONTROL)
-            }
-            VK_MENU => {
-                keyboard.contains(&VK_MENU)
-                    | keyboard.contains(&VK_LMENU)
-                    | keyboard.contains(&VK_RMENU)
-            }
-            _ => keyboard.contains(&key),
-        });
+    pub fn double_tap_term(&self) -> u32 {
+        self.double_tap_term
+    }
 
-        if valid.not() {
-            return None;
+    pub fn combo(&self) -> Option<&Combo> {
+        self.combo.as_ref()
+    }
+
+    pub fn hold(&self) -> Option<&Hold> {
+        self.hold.as_ref()
+    }
+
+    pub fn sticky(&self) -> Option<&Sticky> {
+        self.sticky.as_ref()
+    }
+
+    pub fn text(&self) -> Option<&str> {
+        self.text.as_deref()
+    }
+
+    pub fn steps(&self) -> &[Step] {
+        &self.steps
+    }
+
+    pub fn layer(&self) -> Option<&LayerAction> {
+        self.layer.as_ref()
+    }
+
+    pub fn transparent(&self) -> bool {
+        self.transparent
+    }
+
+    pub fn keep_modifiers(&self) -> bool {
+        self.keep_modifiers
+    }
+
+    pub fn chain(&self) -> bool {
+        self.chain
+    }
+
+    pub fn scope(&self) -> Option<&Scope> {
+        self.scope.as_ref()
+    }
+
+    pub fn clear(&mut self, side: Side) {
+        match side {
+            Side::Input => self.input = None,
+            Side::Output => self.output = None,
         }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.input.is_none() && self.combo.is_none() && self.output.is_none()
+    }
 
-        Some(self.output.clone())
+    /// Sequences are matched by the engine stroke by stroke.
+    pub fn matches(&self, keyboard: &[VirtualKey], key: VirtualKey) -> bool {
+        self.prefix.is_empty()
+            && self
+                .input
+                .as_ref()
+                .is_some_and(|input| match self.matching {
+                    Matching::Subset => input.matches(keyboard, key),
+                    Matching::Exact => input.matches_exactly(keyboard, key),
+                })
     }
 
-    pub fn update(&mut self, side: Side, keyboard: Vec<VIRTUAL_KEY>, key: VIRTUAL_KEY) {
+    pub fn update(&mut self, side: Side, stroke: Stroke) {
         let target = match side {
             Side::Input => &mut self.input,
             Side::Output => &mut self.output,
         };
-        *target = Some(Stroke::new(keyboard, key));
+        *target = Some(stroke);
     }
 
     pub fn get(&self, side: Side) -> Option<&Stroke> {
@@ -180,31 +539,140 @@ impl Mapping {
     }
 }
 
+/// Most specific mapping matching the key press, ties go to the first in the list.
+pub fn resolve<'a>(
+    mappings: &'a [Mapping],
+    keyboard: &[VirtualKey],
+    key: VirtualKey,
+    mut filter: impl FnMut(&Mapping) -> bool,
+) -> Option<(usize, &'a Mapping)> {
+    mappings
+        .iter()
+        .enumerate()
+        .rev()
+        .filter(|(_, mapping)| mapping.matches(keyboard, key) && filter(mapping))
+        .max_by_key(|(_, mapping)| mapping.input.as_ref().map(Stroke::specificity))
+}
+
 #[derive(serde::Serialize, serde::Deserialize)]
 pub struct MappingData {
-    input: Option<StrokeData>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    input: Option<InputData>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    matching: Option<Matching>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    trigger: Option<Trigger>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    double_tap_term: Option<u32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    combo: Vec<VirtualKey>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    combo_term: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     output: Option<StrokeData>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    hold: Option<StrokeData>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tapping_term: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    hold_mode: Option<HoldMode>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sticky: Option<StrokeData>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sticky_timeout: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    text: Option<String>,
+    #[serde(rename = "macro", default, skip_serializing_if = "Vec::is_empty")]
+    steps: Vec<StepData>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    layer: Option<LayerAction>,
+    #[serde(default, skip_serializing_if = "Not::not")]
+    transparent: bool,
+    #[serde(default, skip_serializing_if = "Not::not")]
+    keep_modifiers: bool,
+    #[serde(default, skip_serializing_if = "Not::not")]
+    chain: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    scope: Option<Scope>,
 }
 
 impl From<MappingData> for Mapping {
     fn from(mapping_data: MappingData) -> Self {
+        let mut prefix = mapping_data
+            .input
+            .map(|input| input.0)
+            .unwrap_or_default()
+            .into_iter()
+            .map(Stroke::from)
+            .collect::<Vec<_>>();
+        let input = prefix.pop();
         Self {
-            input: mapping_data.input.map(Into::into),
+            prefix,
+            input,
+            matching: mapping_data.matching.unwrap_or_default(),
+            trigger: mapping_data.trigger.unwrap_or_default(),
+            double_tap_term: mapping_data
+                .double_tap_term
+                .unwrap_or(DEFAULT_DOUBLE_TAP_TERM),
+            combo: mapping_data.combo.is_empty().not().then(|| Combo {
+                keys: mapping_data.combo,
+                term: mapping_data.combo_term.unwrap_or(DEFAULT_COMBO_TERM),
+            }),
             output: mapping_data.output.map(Into::into),
+            hold: mapping_data.hold.map(|stroke| Hold {
+                stroke: stroke.into(),
+                term: mapping_data.tapping_term.unwrap_or(DEFAULT_TAPPING_TERM),
+                mode: mapping_data.hold_mode.unwrap_or_default(),
+            }),
+            sticky: mapping_data.sticky.map(|stroke| Sticky {
+                modifiers: stroke.into(),
+                timeout: mapping_data
+                    .sticky_timeout
+                    .unwrap_or(DEFAULT_STICKY_TIMEOUT),
+            }),
+            text: mapping_data.text,
+            steps: mapping_data.steps.into_iter().map(Into::into).collect(),
+            layer: mapping_data.layer,
+            transparent: mapping_data.transparent,
+            keep_modifiers: mapping_data.keep_modifiers,
+            chain: mapping_data.chain,
+            scope: mapping_data.scope,
         }
     }
 }
 
 impl From<Mapping> for MappingData {
     fn from(mapping: Mapping) -> Self {
+        let input = mapping.input.map(|input| {
+            let strokes = mapping.prefix.into_iter().chain([input]);
+            InputData(strokes.map(Into::into).collect())
+        });
         Self {
-            input: mapping.input.map(Into::into),
+            input,
+            matching: (mapping.matching != Matching::Subset).then_some(mapping.matching),
+            trigger: (mapping.trigger != Trigger::Press).then_some(mapping.trigger),
+            double_tap_term: (mapping.trigger == Trigger::DoubleTap)
+                .then_some(mapping.double_tap_term),
+            combo_term: mapping.combo.as_ref().map(|combo| combo.term),
+            combo: mapping.combo.map(|combo| combo.keys).unwrap_or_default(),
             output: mapping.output.map(Into::into),
+            tapping_term: mapping.hold.as_ref().map(|hold| hold.term),
+            hold_mode: mapping.hold.as_ref().map(|hold| hold.mode),
+            hold: mapping.hold.map(|hold| hold.stroke.into()),
+            sticky_timeout: mapping.sticky.as_ref().map(|sticky| sticky.timeout),
+            sticky: mapping.sticky.map(|sticky| sticky.modifiers.into()),
+            text: mapping.text,
+            steps: mapping.steps.into_iter().map(Into::into).collect(),
+            layer: mapping.layer,
+            transparent: mapping.transparent,
+            keep_modifiers: mapping.keep_modifiers,
+            chain: mapping.chain,
+            scope: mapping.scope,
         }
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum KeyState {
     Released,
     Pressed,
@@ -218,20 +686,11 @@ impl KeyState {
     pub fn released(self) -> bool {
         matches!(self, Self::Released)
     }
-}
-
-pub enum Status {
-    Intercept,
-    Allow,
-    Replace(Vec<INPUT>),
-}
 
-impl std::fmt::Debug for Status {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    pub fn opposite(self) -> Self {
         match self {
-            Self::Intercept => write!(f, "Intercept"),
-            Self::Allow => write!(f, "Allow"),
-            Self::Replace(inputs) => write!(f, "Replace({})", inputs.len()),
+            Self::Pressed => Self::Released,
+            Self::Released => Self::Pressed,
         }
     }
 }
@@ -0,0 +1,124 @@
+use std::{fmt, ops::Not};
+
+use crate::{
+    keys::{Mapping, MappingData},
+    vk_table::VirtualKey,
+};
+
+/// Named set of mappings checked before the mappings of the profile while active.
+#[derive(Debug, Clone)]
+pub struct Layer {
+    pub name: String,
+    pub mappings: Vec<Mapping>,
+}
+
+/// Activates a layer when the input of the mapping is pressed.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LayerAction {
+    /// Active while the key is held.
+    Momentary(String),
+    /// Activated by one press and deactivated by the next.
+    Toggle(String),
+    /// Active for the next key press.
+    OneShot(String),
+}
+
+impl LayerAction {
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Momentary(name) | Self::Toggle(name) | Self::OneShot(name) => name,
+        }
+    }
+}
+
+impl fmt::Display for LayerAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Momentary(name) => write!(f, "layer {}", name),
+            Self::Toggle(name) => write!(f, "toggle {}", name),
+            Self::OneShot(name) => write!(f, "one-shot {}", name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Activation {
+    Momentary(VirtualKey),
+    Toggle,
+    OneShot,
+}
+
+/// Active layers by index, the last activated layer is checked first.
+#[derive(Debug, Default)]
+pub struct LayerStack {
+    layers: Vec<(usize, Activation)>,
+}
+
+impl LayerStack {
+    pub fn activate(&mut self, action: &LayerAction, index: usize, key: VirtualKey) {
+        match action {
+            LayerAction::Momentary(_) => self.layers.push((index, Activation::Momentary(key))),
+            LayerAction::Toggle(_) => {
+                let count = self.layers.len();
+                self.layers
+                    .retain(|layer| *layer != (index, Activation::Toggle));
+                if self.layers.len() == count {
+                    self.layers.push((index, Activation::Toggle));
+                }
+            }
+            LayerAction::OneShot(_) => self.layers.push((index, Activation::OneShot)),
+        }
+    }
+
+    /// Deactivate the momentary layers of the released key.
+    pub fn release(&mut self, key: VirtualKey) {
+        self.layers
+            .retain(|(_, activation)| *activation != Activation::Momentary(key));
+    }
+
+    /// Deactivate the one-shot layers after a key press used them.
+    pub fn consume_one_shot(&mut self) {
+        self.layers
+            .retain(|(_, activation)| *activation != Activation::OneShot);
+    }
+
+    pub fn clear(&mut self) {
+        self.layers.clear();
+    }
+
+    /// Indices of the active layers, top first.
+    pub fn indices(&self) -> Vec<usize> {
+        self.layers.iter().rev().map(|(index, _)| *index).collect()
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct LayerData {
+    pub name: String,
+    #[serde(default)]
+    pub mappings: Vec<MappingData>,
+}
+
+impl From<LayerData> for Layer {
+    fn from(layer_data: LayerData) -> Self {
+        Self {
+            name: layer_data.name,
+            mappings: layer_data.mappings.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl From<Layer> for LayerData {
+    fn from(layer: Layer) -> Self {
+        Self {
+            name: layer.name,
+            mappings: layer
+                .mappings
+                .into_iter()
+                .filter(|mapping| mapping.is_empty().not())
+                .map(Into::into)
+                .collect(),
+        }
+    }
+}
@@ -0,0 +1,268 @@
+use std::ops::Not;
+
+use crate::{
+    keys::{self, Mapping, Side, Stroke},
+    vk_table::*,
+};
+
+/// Problem of a mapping found without running it, other mappings are referenced by index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Warning {
+    /// Same input as an earlier mapping.
+    Duplicate(usize),
+    /// Never used, the other mapping wins for the input.
+    Shadowed(usize),
+    /// The output is the input of the other mapping, which is only followed with `chain`.
+    Feedback(usize),
+    /// Following the chained outputs leads back to the mapping.
+    Cycle,
+    /// The chained outputs follow more mappings than the chain depth.
+    TooLong(u32),
+    /// Part of the output which can not be sent as keyboard input.
+    Unsendable(VirtualKey),
+}
+
+impl Warning {
+    pub fn message(self, mappings: &[Mapping]) -> String {
+        match self {
+            Self::Duplicate(other) => format!("duplicate of {}", describe(mappings, other)),
+            Self::Shadowed(other) => format!("shadowed by {}", describe(mappings, other)),
+            Self::Feedback(other) => {
+                format!("output is the input of {}", describe(mappings, other))
+            }
+            Self::Cycle => "outputs form a cycle".into(),
+            Self::TooLong(depth) => format!("chain follows more than {} mappings", depth),
+            Self::Unsendable(key) => format!("{} can not be sent", key),
+        }
+    }
+}
+
+/// Mapping number and input, for example `#2 Control+A`.
+pub fn describe(mappings: &[Mapping], index: usize) -> String {
+    let Some(mapping) = mappings.get(index) else {
+        return format!("#{}", index + 1);
+    };
+    let input = match (mapping.get(Side::Input), mapping.combo()) {
+        (_, Some(combo)) => combo.to_string(),
+        (Some(input), None) => mapping
+            .prefix()
+            .iter()
+            .chain([input])
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", "),
+        (None, None) => String::new(),
+    };
+    format!("#{} {}", index + 1, input)
+}
+
+/// Warnings for the mappings of one list, with the index of the affected mapping.
+pub fn lint(mappings: &[Mapping], chain_depth: u32) -> Vec<(usize, Warning)> {
+    let mut warnings = Vec::new();
+    for (index, mapping) in mappings.iter().enumerate() {
+        if mapping.is_empty() {
+            continue;
+        }
+        if let Some(other) = mappings[..index]
+            .iter()
+            .position(|other| same_input(mapping, other))
+        {
+            warnings.push((index, Warning::Duplicate(other)));
+        } else if let Some(other) = shadowed_by(mappings, index) {
+            warnings.push((index, Warning::Shadowed(other)));
+        }
+
+        if mapping.chain() {
+            match chain_length(mappings, index) {
+                None => warnings.push((index, Warning::Cycle)),
+                Some(length) if length > chain_depth as usize => {
+                    warnings.push((index, Warning::TooLong(chain_depth)))
+                }
+                Some(_) => {}
+            }
+        } else if let Some(other) = feeds_into(mappings, index) {
+            warnings.push((index, Warning::Feedback(other)));
+        }
+
+        for key in output_strokes(mapping).flat_map(Stroke::keys) {
+            if sendable(key).not() && warnings.contains(&(index, Warning::Unsendable(key))).not() {
+                warnings.push((index, Warning::Unsendable(key)));
+            }
+        }
+    }
+    warnings
+}
+
+fn same_input(mapping: &Mapping, other: &Mapping) -> bool {
+    if mapping.scope() != other.scope() {
+        return false;
+    }
+    match (mapping.combo(), other.combo()) {
+        (Some(combo), Some(other)) => {
+            let mut keys = combo.keys.clone();
+            let mut other_keys = other.keys.clone();
+            keys.sort();
+            other_keys.sort();
+            keys == other_keys
+        }
+        (None, None) => {
+            mapping.get(Side::Input).is_some()
+                && mapping.get(Side::Input) == other.get(Side::Input)
+                && mapping.prefix() == other.prefix()
+                && mapping.matching() == other.matching()
+                && mapping.trigger() == other.trigger()
+        }
+        _ => false,
+    }
+}
+
+/// Mapping used instead of the one at `index` when its input is pressed.
+fn shadowed_by(mappings: &[Mapping], index: usize) -> Option<usize> {
+    let mapping = &mappings[index];
+    if mapping.prefix().is_empty().not() {
+        return None;
+    }
+    let input = mapping.get(Side::Input)?;
+    // the first stroke of a sequence is held back before any other mapping is looked up
+    if let Some(sequence) = mappings.iter().position(|other| {
+        other.sequence().is_some_and(|strokes| {
+            input.key().is_modifier().not() && strokes[0].matches(input.keyboard(), input.key())
+        })
+    }) {
+        return Some(sequence);
+    }
+    let (winner, _) = keys::resolve(mappings, input.keyboard(), input.key(), |other| {
+        other.scope().is_none() || other.scope() == mapping.scope()
+    })?;
+    (winner != index).then_some(winner)
+}
+
+/// Mapping which would match the output of the one at `index`.
+fn feeds_into(mappings: &[Mapping], index: usize) -> Option<usize> {
+    let mapping = &mappings[index];
+    if mapping.text().is_some() || mapping.steps().is_empty().not() {
+        return None;
+    }
+    let output = mapping.get(Side::Output)?;
+    let (other, _) = keys::resolve(mappings, output.keyboard(), output.key(), |other| {
+        other.scope().is_none() || mapping.scope().is_none() || other.scope() == mapping.scope()
+    })?;
+    Some(other)
+}
+
+/// Number of mappings followed from the chained output of the one at `index`,
+/// `None` if they lead back to it.
+fn chain_length(mappings: &[Mapping], index: usize) -> Option<usize> {
+    let mut visited = vec![index];
+    let mut current = index;
+    while mappings[current].chain() {
+        let Some(next) = feeds_into(mappings, current) else {
+            break;
+        };
+        if next == index {
+            return None;
+        }
+        // a cycle of the other mappings, reported for them
+        if visited.contains(&next) {
+            break;
+        }
+        visited.push(next);
+        current = next;
+    }
+    Some(visited.len() - 1)
+}
+
+fn output_strokes(mapping: &Mapping) -> impl Iterator<Item = &Stroke> {
+    mapping
+        .get(Side::Output)
+        .into_iter()
+        .chain(mapping.hold().map(|hold| &hold.stroke))
+        .chain(mapping.steps().iter().filter_map(|step| step.stroke()))
+}
+
+/// Mouse buttons and unknown keys are not keyboard input.
+fn sendable(key: VirtualKey) -> bool {
+    key.is_known()
+        && matches!(
+            key,
+            VK_LBUTTON | VK_RBUTTON | VK_MBUTTON | VK_XBUTTON1 | VK_XBUTTON2
+        )
+        .not()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{keys::MappingData, profile::DEFAULT_CHAIN_DEPTH};
+
+    fn mappings(json: &str) -> Vec<Mapping> {
+        serde_json::from_str::<Vec<MappingData>>(json)
+            .unwrap()
+            .into_iter()
+            .map(Mapping::from)
+            .collect()
+    }
+
+    #[test]
+    fn swapped_keys_feed_back() {
+        let mappings =
+            mappings(r#"[{ "input": "A", "output": "B" }, { "input": "B", "output": "A" }]"#);
+        let warnings = lint(&mappings, DEFAULT_CHAIN_DEPTH);
+        assert_eq!(
+            warnings,
+            [(0, Warning::Feedback(1)), (1, Warning::Feedback(0))]
+        );
+    }
+
+    #[test]
+    fn valid_chain() {
+        let mappings = mappings(
+            r#"[
+                { "input": "A", "output": "B", "chain": true },
+                { "input": "B", "output": "C", "chain": true },
+                { "input": "C", "output": "D" }
+            ]"#,
+        );
+        assert_eq!(lint(&mappings, DEFAULT_CHAIN_DEPTH), Vec::new());
+        assert_eq!(lint(&mappings, 1), vec![(0, Warning::TooLong(1))]);
+    }
+
+    #[test]
+    fn chained_cycle() {
+        let mappings = mappings(
+            r#"[
+                { "input": "A", "output": "B", "chain": true },
+                { "input": "B", "output": "A", "chain": true },
+                { "input": "C", "output": "A", "chain": true }
+            ]"#,
+        );
+        assert_eq!(
+            lint(&mappings, DEFAULT_CHAIN_DEPTH),
+            [(0, Warning::Cycle), (1, Warning::Cycle)]
+        );
+    }
+
+    #[test]
+    fn sequence_shadows_its_first_stroke() {
+        let mappings = mappings(
+            r#"[
+                { "input": "Control+X", "output": "Delete" },
+                { "input": ["Control+X", "Control+S"], "output": "F12" },
+                { "input": "Control+S", "output": "F2" }
+            ]"#,
+        );
+        assert_eq!(
+            lint(&mappings, DEFAULT_CHAIN_DEPTH),
+            [(0, Warning::Shadowed(1))]
+        );
+    }
+
+    #[test]
+    fn mouse_buttons_are_unsendable() {
+        let mappings = mappings(r#"[{ "input": "A", "output": "LButton" }]"#);
+        assert_eq!(
+            lint(&mappings, DEFAULT_CHAIN_DEPTH),
+            vec![(0, Warning::Unsendable(VK_LBUTTON))]
+        );
+    }
+}
@@ -0,0 +1,73 @@
+use std::fmt;
+
+use crate::keys::{Stroke, StrokeData};
+
+/// Step of a macro output.
+#[derive(Debug, Clone)]
+pub enum Step {
+    /// Press and release the stroke.
+    Stroke(Stroke),
+    /// Press the keys of the stroke without releasing them.
+    Down(Stroke),
+    /// Release the keys of the stroke.
+    Up(Stroke),
+    /// Type the characters.
+    Text(String),
+    /// Wait the milliseconds before the next step.
+    Wait(u32),
+}
+
+impl Step {
+    pub fn stroke(&self) -> Option<&Stroke> {
+        match self {
+            Self::Stroke(stroke) | Self::Down(stroke) | Self::Up(stroke) => Some(stroke),
+            Self::Text(_) | Self::Wait(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Stroke(stroke) => write!(f, "{}", stroke),
+            Self::Down(stroke) => write!(f, "down {}", stroke),
+            Self::Up(stroke) => write!(f, "up {}", stroke),
+            Self::Text(text) => write!(f, "{:?}", text),
+            Self::Wait(milliseconds) => write!(f, "wait {}ms", milliseconds),
+        }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StepData {
+    Stroke(StrokeData),
+    Down(StrokeData),
+    Up(StrokeData),
+    Text(String),
+    Wait(u32),
+}
+
+impl From<StepData> for Step {
+    fn from(step_data: StepData) -> Self {
+        match step_data {
+            StepData::Stroke(stroke) => Self::Stroke(stroke.into()),
+            StepData::Down(stroke) => Self::Down(stroke.into()),
+            StepData::Up(stroke) => Self::Up(stroke.into()),
+            StepData::Text(text) => Self::Text(text),
+            StepData::Wait(milliseconds) => Self::Wait(milliseconds),
+        }
+    }
+}
+
+impl From<Step> for StepData {
+    fn from(step: Step) -> Self {
+        match step {
+            Step::Stroke(stroke) => Self::Stroke(stroke.into()),
+            Step::Down(stroke) => Self::Down(stroke.into()),
+            Step::Up(stroke) => Self::Up(stroke.into()),
+            Step::Text(text) => Self::Text(text),
+            Step::Wait(milliseconds) => Self::Wait(milliseconds),
+        }
+    }
+}
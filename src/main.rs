@@ -1,20 +1,48 @@
 #![allow(static_mut_refs)]
 
 mod assets;
+mod backend;
+mod cli;
+mod combo;
+mod config;
+mod engine;
 mod global;
 mod keys;
+mod layer;
+mod lint;
+mod macros;
+mod profile;
+mod scope;
+mod sequence;
+mod settings;
+mod sticky;
+mod tap_hold;
 mod theme;
 mod title_bar;
 mod ui;
 mod vk_table;
 
+use std::process::ExitCode;
+
 use assets::BundledAssets;
+use cli::Command;
 use global::Global;
 use gpui::*;
 use ui::UI;
 
-fn main() {
-    let hook = Global::install_hook();
+fn main() -> ExitCode {
+    match Command::parse(std::env::args().skip(1)) {
+        Ok(Command::Gui) => {}
+        Ok(Command::Run { config, check }) => return cli::run(config, check),
+        Ok(Command::Lint { config }) => return cli::lint_config(config),
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, cli::USAGE);
+            return ExitCode::FAILURE;
+        }
+    }
+
+    Global::restore();
+    let hook = backend::install_hook();
 
     App::new().with_assets(BundledAssets).run(|cx| {
         let options = WindowOptions {
@@ -30,5 +58,6 @@ fn main() {
         cx.open_window(options, UI::new).unwrap();
     });
 
-    Global::delete_hook(hook);
+    backend::delete_hook(hook);
+    ExitCode::SUCCESS
 }
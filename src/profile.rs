@@ -0,0 +1,173 @@
+use std::ops::Not;
+
+use crate::{
+    keys::{Mapping, MappingData, Stroke, StrokeData},
+    layer::{Layer, LayerData},
+    vk_table::VirtualKey,
+};
+
+#[derive(Debug, Clone)]
+pub struct Profile {
+    pub name: String,
+    pub hotkey: Option<Stroke>,
+    pub mappings: Vec<Mapping>,
+    pub layers: Vec<Layer>,
+}
+
+impl Profile {
+    pub fn new(name: String, mappings: Vec<Mapping>) -> Self {
+        Self {
+            name,
+            hotkey: None,
+            mappings,
+            layers: Vec::new(),
+        }
+    }
+
+    pub fn layer_index(&self, name: &str) -> Option<usize> {
+        self.layers.iter().position(|layer| layer.name == name)
+    }
+}
+
+pub const DEFAULT_CHAIN_DEPTH: u32 = 4;
+
+/// All profiles of a configuration, exactly one of them is active.
+#[derive(Debug, Clone)]
+pub struct Profiles {
+    profiles: Vec<Profile>,
+    active: usize,
+    cycle: Option<Stroke>,
+    /// Number of chained mappings followed after the first one.
+    chain_depth: Option<u32>,
+    /// Input injected by other programs is not remapped.
+    skip_injected: bool,
+}
+
+impl Default for Profiles {
+    fn default() -> Self {
+        Self::new(Vec::new(), None)
+    }
+}
+
+impl Profiles {
+    pub fn new(mut profiles: Vec<Profile>, cycle: Option<Stroke>) -> Self {
+        if profiles.is_empty() {
+            profiles.push(Profile::new("Default".into(), Vec::new()));
+        }
+        Self {
+            profiles,
+            active: 0,
+            cycle,
+            chain_depth: None,
+            skip_injected: false,
+        }
+    }
+
+    pub fn set_skip_injected(&mut self, skip_injected: bool) {
+        self.skip_injected = skip_injected;
+    }
+
+    pub fn skip_injected(&self) -> bool {
+        self.skip_injected
+    }
+
+    pub fn set_chain_depth(&mut self, chain_depth: Option<u32>) {
+        self.chain_depth = chain_depth;
+    }
+
+    /// Configured value, [`Profiles::chain_depth`] includes the default.
+    pub fn configured_chain_depth(&self) -> Option<u32> {
+        self.chain_depth
+    }
+
+    pub fn chain_depth(&self) -> u32 {
+        self.chain_depth.unwrap_or(DEFAULT_CHAIN_DEPTH)
+    }
+
+    pub fn list(&self) -> &[Profile] {
+        &self.profiles
+    }
+
+    pub fn list_mut(&mut self) -> &mut [Profile] {
+        &mut self.profiles
+    }
+
+    pub fn cycle(&self) -> Option<&Stroke> {
+        self.cycle.as_ref()
+    }
+
+    pub fn active_index(&self) -> usize {
+        self.active
+    }
+
+    pub fn active(&self) -> &Profile {
+        &self.profiles[self.active]
+    }
+
+    pub fn active_mut(&mut self) -> &mut Profile {
+        &mut self.profiles[self.active]
+    }
+
+    pub fn select(&mut self, index: usize) {
+        if index < self.profiles.len() {
+            self.active = index;
+        }
+    }
+
+    pub fn next(&mut self) {
+        self.active = (self.active + 1) % self.profiles.len();
+    }
+
+    /// Profile selected by the hotkey pressed with `key`, the cycle hotkey is checked first.
+    pub fn switch_target(&self, keyboard: &[VirtualKey], key: VirtualKey) -> Option<usize> {
+        if let Some(cycle) = &self.cycle {
+            if cycle.matches(keyboard, key) {
+                return Some((self.active + 1) % self.profiles.len());
+            }
+        }
+        self.profiles.iter().position(|profile| {
+            profile
+                .hotkey
+                .as_ref()
+                .is_some_and(|hotkey| hotkey.matches(keyboard, key))
+        })
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct ProfileData {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hotkey: Option<StrokeData>,
+    #[serde(default)]
+    pub mappings: Vec<MappingData>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub layers: Vec<LayerData>,
+}
+
+impl From<ProfileData> for Profile {
+    fn from(profile_data: ProfileData) -> Self {
+        Self {
+            name: profile_data.name,
+            hotkey: profile_data.hotkey.map(Into::into),
+            mappings: profile_data.mappings.into_iter().map(Into::into).collect(),
+            layers: profile_data.layers.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl From<Profile> for ProfileData {
+    fn from(profile: Profile) -> Self {
+        Self {
+            name: profile.name,
+            hotkey: profile.hotkey.map(Into::into),
+            mappings: profile
+                .mappings
+                .into_iter()
+                .filter(|mapping| mapping.is_empty().not())
+                .map(Into::into)
+                .collect(),
+            layers: profile.layers.into_iter().map(Into::into).collect(),
+        }
+    }
+}
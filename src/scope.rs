@@ -0,0 +1,50 @@
+use std::fmt;
+
+/// Restricts a mapping to some applications.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    /// File name of the executable, for example `"code.exe"`.
+    Process(String),
+    /// Part of the window title.
+    Title(String),
+}
+
+impl Scope {
+    pub fn matches(&self, window: &WindowInfo) -> bool {
+        match self {
+            Self::Process(process) => window.process.eq_ignore_ascii_case(process),
+            Self::Title(title) => window.title.to_lowercase().contains(&title.to_lowercase()),
+        }
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Process(process) => write!(f, "in {}", process),
+            Self::Title(title) => write!(f, "in \"{}\"", title),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WindowInfo {
+    pub process: String,
+    pub title: String,
+}
+
+/// Source for the foreground window, implemented per platform.
+pub trait WindowProvider: fmt::Debug + Send {
+    fn foreground(&self) -> Option<WindowInfo>;
+}
+
+/// Provider without any windows, scoped mappings never match.
+#[derive(Debug)]
+pub struct NoWindow;
+
+impl WindowProvider for NoWindow {
+    fn foreground(&self) -> Option<WindowInfo> {
+        None
+    }
+}
@@ -0,0 +1,213 @@
+use std::fmt;
+
+use crate::{
+    keys::{KeyState, Mapping, Stroke},
+    profile::Profile,
+    vk_table::VirtualKey,
+};
+
+/// Milliseconds between the strokes of a sequence.
+pub const SEQUENCE_TIMEOUT: u32 = 1000;
+
+/// Position of a mapping, the layer index or `None` for the profile.
+pub type MappingIndex = (Option<usize>, usize);
+
+/// Prefix trie of the sequence mappings of a profile.
+#[derive(Debug, Default)]
+pub struct SequenceTrie {
+    children: Vec<(Stroke, SequenceTrie)>,
+    /// Mappings of the sequences ending here.
+    mappings: Vec<MappingIndex>,
+}
+
+impl SequenceTrie {
+    pub fn new(profile: &Profile) -> Self {
+        let mut trie = Self::default();
+        let layers = profile.layers.iter().enumerate().flat_map(|(layer, data)| {
+            data.mappings
+                .iter()
+                .enumerate()
+                .map(move |(index, mapping)| ((Some(layer), index), mapping))
+        });
+        for (index, mapping) in profile
+            .mappings
+            .iter()
+            .enumerate()
+            .map(|(index, mapping)| ((None, index), mapping))
+            .chain(layers)
+        {
+            if let Some(sequence) = mapping.sequence() {
+                trie.insert(&sequence, index);
+            }
+        }
+        trie
+    }
+
+    fn insert(&mut self, strokes: &[Stroke], index: MappingIndex) {
+        let Some((first, rest)) = strokes.split_first() else {
+            self.mappings.push(index);
+            return;
+        };
+        let position = match self.children.iter().position(|(stroke, _)| stroke == first) {
+            Some(position) => position,
+            None => {
+                self.children.push((first.clone(), Self::default()));
+                self.children.len() - 1
+            }
+        };
+        self.children[position].1.insert(rest, index);
+    }
+
+    /// Node reached by following the child indices.
+    pub fn node(&self, path: &[usize]) -> &Self {
+        path.iter()
+            .fold(self, |node, index| &node.children[*index].1)
+    }
+
+    /// Index of the child for the stroke.
+    pub fn step(&self, keyboard: &[VirtualKey], key: VirtualKey) -> Option<usize> {
+        self.children
+            .iter()
+            .position(|(stroke, _)| stroke.matches(keyboard, key))
+    }
+
+    pub fn is_leaf(&self) -> bool {
+        self.children.is_empty()
+    }
+
+    pub fn mappings(&self) -> &[MappingIndex] {
+        &self.mappings
+    }
+}
+
+/// Resolve a position from the trie.
+pub fn mapping(profile: &Profile, (layer, index): MappingIndex) -> Option<&Mapping> {
+    match layer {
+        Some(layer) => profile.layers.get(layer)?.mappings.get(index),
+        None => profile.mappings.get(index),
+    }
+}
+
+/// Strokes of a sequence typed so far.
+#[derive(Debug)]
+pub struct PendingSequence {
+    /// Child indices from the root of the trie.
+    pub path: Vec<usize>,
+    /// Presses and releases of the prefix, replayed if the sequence fails.
+    pub events: Vec<(Stroke, KeyState)>,
+    /// Time of the last stroke.
+    time: u32,
+}
+
+impl PendingSequence {
+    pub fn new(child: usize, stroke: Stroke, time: u32) -> Self {
+        Self {
+            path: vec![child],
+            events: vec![(stroke, KeyState::Pressed)],
+            time,
+        }
+    }
+
+    pub fn push(&mut self, child: usize, stroke: Stroke, time: u32) {
+        self.path.push(child);
+        self.events.push((stroke, KeyState::Pressed));
+        self.time = time;
+    }
+
+    pub fn timed_out(&self, time: u32) -> bool {
+        time.wrapping_sub(self.time) >= SEQUENCE_TIMEOUT
+    }
+
+    /// Keys pressed during the sequence and not released yet.
+    pub fn held(&self) -> Vec<VirtualKey> {
+        let mut keys = Vec::new();
+        for (stroke, state) in self.events.iter() {
+            match state {
+                KeyState::Pressed => keys.push(stroke.key()),
+                KeyState::Released => keys.retain(|key| *key != stroke.key()),
+            }
+        }
+        keys
+    }
+}
+
+impl fmt::Display for PendingSequence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let strokes = self
+            .events
+            .iter()
+            .filter(|(_, state)| state.pressed())
+            .map(|(stroke, _)| stroke.to_string())
+            .collect::<Vec<_>>();
+        write!(f, "{}, ...", strokes.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Not;
+
+    use super::*;
+    use crate::keys::MappingData;
+
+    fn key(name: &str) -> VirtualKey {
+        VirtualKey::from_name(name).unwrap()
+    }
+
+    fn stroke(name: &str) -> Stroke {
+        Stroke::new(Vec::new(), key(name))
+    }
+
+    fn trie(json: &str) -> SequenceTrie {
+        let mappings = serde_json::from_str::<Vec<MappingData>>(json)
+            .unwrap()
+            .into_iter()
+            .map(Mapping::from)
+            .collect();
+        SequenceTrie::new(&Profile::new("Default".to_owned(), mappings))
+    }
+
+    #[test]
+    fn trie_shares_prefixes() {
+        let trie = trie(
+            r#"[
+                { "input": "A", "output": "B" },
+                { "input": ["Control+X", "S"], "output": "F2" },
+                { "input": ["Control+X", "C"], "output": "F3" },
+                { "input": ["Control+X", "C", "C"], "output": "F4" }
+            ]"#,
+        );
+        assert_eq!(trie.step(&[], key("A")), None);
+        assert_eq!(trie.step(&[], key("X")), None);
+        let control = [key("LControl")];
+        assert_eq!(trie.step(&control, key("X")), Some(0));
+
+        let prefix = trie.node(&[0]);
+        assert!(prefix.is_leaf().not());
+        assert_eq!(prefix.mappings(), []);
+        assert_eq!(prefix.step(&[], key("S")), Some(0));
+        assert_eq!(prefix.step(&[], key("C")), Some(1));
+        assert!(trie.node(&[0, 0]).is_leaf());
+        assert_eq!(trie.node(&[0, 0]).mappings(), [(None, 1)]);
+
+        // a complete sequence which is the start of a longer one
+        let shorter = trie.node(&[0, 1]);
+        assert!(shorter.is_leaf().not());
+        assert_eq!(shorter.mappings(), [(None, 2)]);
+        assert_eq!(trie.node(&[0, 1, 0]).mappings(), [(None, 3)]);
+    }
+
+    #[test]
+    fn pending_sequence() {
+        let mut pending = PendingSequence::new(0, stroke("G"), 100);
+        assert_eq!(pending.held(), [key("G")]);
+        pending.events.push((stroke("G"), KeyState::Released));
+        pending.push(1, stroke("H"), 600);
+        assert_eq!(pending.path, [0, 1]);
+        assert_eq!(pending.held(), [key("H")]);
+        assert_eq!(pending.to_string(), "G, H, ...");
+
+        assert!(pending.timed_out(600 + SEQUENCE_TIMEOUT - 1).not());
+        assert!(pending.timed_out(600 + SEQUENCE_TIMEOUT));
+    }
+}
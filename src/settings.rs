@@ -0,0 +1,38 @@
+use std::{fs::File, io, path::PathBuf};
+
+use crate::config;
+
+/// Application state that survives a restart.
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct Settings {
+    pub path: Option<PathBuf>,
+}
+
+impl Settings {
+    pub fn load() -> Self {
+        let Some(path) = settings_path() else {
+            return Self::default();
+        };
+        let Ok(file) = File::open(path) else {
+            return Self::default();
+        };
+        serde_json::from_reader(file).unwrap_or_default()
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let Some(path) = settings_path() else {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "No configuration directory",
+            ));
+        };
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        config::write_atomic(&path, |file| Ok(serde_json::to_writer(file, self)?))
+    }
+}
+
+fn settings_path() -> Option<PathBuf> {
+    Some(dirs::config_dir()?.join("ein-key").join("settings.json"))
+}
@@ -0,0 +1,30 @@
+use std::ops::Not;
+
+use crate::{keys::Sticky, vk_table::VirtualKey};
+
+/// Modifiers of a tapped sticky key, waiting for the next key.
+#[derive(Debug)]
+pub struct Latch {
+    pub key: VirtualKey,
+    pub sticky: Sticky,
+    /// Kept for all keys until the sticky key is tapped again.
+    pub locked: bool,
+    /// Time of the release of the sticky key.
+    time: u32,
+}
+
+impl Latch {
+    pub fn new(key: VirtualKey, sticky: Sticky, time: u32) -> Self {
+        Self {
+            key,
+            sticky,
+            locked: false,
+            time,
+        }
+    }
+
+    /// Not used in time, the next key is sent without the modifiers.
+    pub fn expired(&self, time: u32) -> bool {
+        self.locked.not() && time.wrapping_sub(self.time) >= self.sticky.timeout
+    }
+}
@@ -0,0 +1,191 @@
+use crate::{
+    engine::KeyEvent,
+    keys::{Hold, HoldMode, Stroke},
+    vk_table::VirtualKey,
+};
+
+/// Dual-role key waiting for the decision between tap and hold.
+#[derive(Debug)]
+pub struct PendingHold {
+    pub key: VirtualKey,
+    pub tap: Option<Stroke>,
+    pub hold: Hold,
+    start: u32,
+    /// Events of other keys while undecided, replayed after the decision.
+    pub buffered: Vec<KeyEvent>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    /// The event was consumed or buffered.
+    Wait,
+    /// The event released the key in time.
+    Tap,
+    /// The buffered events and then the event itself still need to be handled.
+    Hold,
+}
+
+impl PendingHold {
+    pub fn new(event: KeyEvent, tap: Option<Stroke>, hold: Hold) -> Self {
+        Self {
+            key: event.key,
+            tap,
+            hold,
+            start: event.time,
+            buffered: Vec::new(),
+        }
+    }
+
+    /// Advance with the next event, [`PendingHold::timed_out`] covers the time without events.
+    pub fn decide(&mut self, event: KeyEvent) -> Decision {
+        if self.timed_out(event.time) {
+            return Decision::Hold;
+        }
+
+        if event.key == self.key {
+            return match event.state.released() {
+                true => Decision::Tap,
+                // autorepeat
+                false => Decision::Wait,
+            };
+        }
+
+        match self.hold.mode {
+            HoldMode::HoldOnOtherKeyPress if event.state.pressed() => return Decision::Hold,
+            HoldMode::PermissiveHold if event.state.released() && self.was_pressed(event.key) => {
+                return Decision::Hold
+            }
+            _ => {}
+        }
+
+        self.buffered.push(event);
+        Decision::Wait
+    }
+
+    /// Held for the tapping term at `time`.
+    pub fn timed_out(&self, time: u32) -> bool {
+        time.wrapping_sub(self.start) >= self.hold.term
+    }
+
+    fn was_pressed(&self, key: VirtualKey) -> bool {
+        self.buffered
+            .iter()
+            .any(|event| event.key == key && event.state.pressed())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Not;
+
+    use super::*;
+    use crate::keys::KeyState;
+
+    fn key(name: &str) -> VirtualKey {
+        VirtualKey::from_name(name).unwrap()
+    }
+
+    fn event(name: &str, state: KeyState, time: u32) -> KeyEvent {
+        KeyEvent {
+            key: key(name),
+            state,
+            injected: false,
+            time,
+        }
+    }
+
+    fn caps_lock(mode: HoldMode) -> PendingHold {
+        let hold = Hold {
+            stroke: Stroke::new(Vec::new(), key("LControl")),
+            term: 200,
+            mode,
+        };
+        let tap = Stroke::new(Vec::new(), key("Esc"));
+        PendingHold::new(event("CapsLock", KeyState::Pressed, 0), Some(tap), hold)
+    }
+
+    #[test]
+    fn timeout_holds_after_the_term() {
+        let mut pending = caps_lock(HoldMode::Timeout);
+        assert_eq!(
+            pending.decide(event("J", KeyState::Pressed, 10)),
+            Decision::Wait
+        );
+        assert_eq!(
+            pending.decide(event("J", KeyState::Released, 20)),
+            Decision::Wait
+        );
+        let repeat = event("CapsLock", KeyState::Pressed, 199);
+        assert_eq!(pending.decide(repeat), Decision::Wait);
+        assert!(pending.timed_out(199).not());
+        assert!(pending.timed_out(200));
+        let repeat = event("CapsLock", KeyState::Pressed, 200);
+        assert_eq!(pending.decide(repeat), Decision::Hold);
+        assert_eq!(pending.buffered.len(), 2);
+    }
+
+    #[test]
+    fn timeout_tap_within_the_term() {
+        let mut pending = caps_lock(HoldMode::Timeout);
+        let release = event("CapsLock", KeyState::Released, 199);
+        assert_eq!(pending.decide(release), Decision::Tap);
+    }
+
+    #[test]
+    fn permissive_hold_on_a_tap_of_another_key() {
+        let mut pending = caps_lock(HoldMode::PermissiveHold);
+        assert_eq!(
+            pending.decide(event("J", KeyState::Pressed, 10)),
+            Decision::Wait
+        );
+        assert_eq!(
+            pending.decide(event("J", KeyState::Released, 20)),
+            Decision::Hold
+        );
+        assert_eq!(pending.buffered, [event("J", KeyState::Pressed, 10)]);
+    }
+
+    #[test]
+    fn permissive_hold_rolling_press_is_a_tap() {
+        let mut pending = caps_lock(HoldMode::PermissiveHold);
+        assert_eq!(
+            pending.decide(event("J", KeyState::Pressed, 10)),
+            Decision::Wait
+        );
+        let release = event("CapsLock", KeyState::Released, 20);
+        assert_eq!(pending.decide(release), Decision::Tap);
+        assert_eq!(pending.buffered, [event("J", KeyState::Pressed, 10)]);
+    }
+
+    #[test]
+    fn permissive_hold_ignores_keys_pressed_before() {
+        let mut pending = caps_lock(HoldMode::PermissiveHold);
+        assert_eq!(
+            pending.decide(event("J", KeyState::Released, 10)),
+            Decision::Wait
+        );
+        let release = event("CapsLock", KeyState::Released, 20);
+        assert_eq!(pending.decide(release), Decision::Tap);
+    }
+
+    #[test]
+    fn hold_on_other_key_press() {
+        let mut pending = caps_lock(HoldMode::HoldOnOtherKeyPress);
+        assert_eq!(
+            pending.decide(event("J", KeyState::Pressed, 10)),
+            Decision::Hold
+        );
+        assert!(pending.buffered.is_empty());
+    }
+
+    #[test]
+    fn hold_on_other_key_press_tap_alone() {
+        let mut pending = caps_lock(HoldMode::HoldOnOtherKeyPress);
+        assert_eq!(
+            pending.decide(event("J", KeyState::Released, 10)),
+            Decision::Wait
+        );
+        let release = event("CapsLock", KeyState::Released, 20);
+        assert_eq!(pending.decide(release), Decision::Tap);
+    }
+}
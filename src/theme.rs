@@ -9,6 +9,8 @@ pub enum Color {
     Foreground,
     ForegroundSelected,
     ForegroundDisabled,
+
+    Error,
 }
 
 const HUE: f32 = 0.6;
@@ -24,6 +26,8 @@ impl From<Color> for Hsla {
             Color::Foreground => hsla(HUE, 0.1, 0.8, 1.0),
             Color::ForegroundSelected => hsla(HUE, 1.0, 1.0, 1.0),
             Color::ForegroundDisabled => hsla(HUE, 0.1, 0.4, 1.0),
+
+            Color::Error => hsla(0.0, 0.6, 0.35, 1.0),
         }
     }
 }
@@ -70,6 +70,29 @@ pub fn render_title_bar(active: bool, cx: &mut ViewContext<UI>) -> impl IntoElem
                 .px_3()
                 .child("Import"),
         )
+        .child(div().w_10())
+        .child(
+            div()
+                .when(active, |div| {
+                    div.on_mouse_down(
+                        MouseButton::Left,
+                        cx.listener(|_, _, _| Global::next_profile()),
+                    )
+                })
+                .when(active.not(), |div| {
+                    div.text_color(Color::ForegroundDisabled)
+                })
+                .px_3()
+                .child(format!("Profile: {}", Global::profile_name())),
+        )
+        .when_some(Global::pending_sequence(), |row, sequence| {
+            row.child(
+                div()
+                    .px_3()
+                    .text_color(Color::ForegroundDisabled)
+                    .child(sequence),
+            )
+        })
         .child(div().flex_1())
         .child(WindowsCaptionButton::new(
             "minimize",
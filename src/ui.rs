@@ -1,4 +1,4 @@
-use std::path::PathBuf;
+use std::{ops::Not, path::PathBuf};
 
 use gpui::*;
 use prelude::FluentBuilder;
@@ -6,9 +6,10 @@ use prelude::FluentBuilder;
 use crate::{
     global::{
         Global, GlobalChanged, GlobalCheck, GlobalChecker, GlobalDelete, GlobalExitEdit,
-        GlobalSelect,
+        GlobalSelect, GlobalToggleMatching,
     },
-    keys::{Side, Stroke},
+    keys::{Side, Stroke, Trigger},
+    lint::lint,
     theme::Color,
     title_bar::render_title_bar,
 };
@@ -83,6 +84,14 @@ impl UI {
         })
         .detach();
 
+        cx.subscribe(
+            &global_checker,
+            move |_, event: &GlobalToggleMatching, _cx| {
+                Global::toggle_matching(event.0);
+            },
+        )
+        .detach();
+
         cx.subscribe(&global_checker, move |_, _event: &GlobalExitEdit, _cx| {
             Global::exit_edit();
         })
@@ -118,6 +127,9 @@ impl Render for UI {
             .h_full()
             .bg(Color::Background)
             .child(render_title_bar(menu_interactivity.normal(), cx))
+            .when_some(Global::error(), |div, error| {
+                div.child(render_error(error, cx))
+            })
             .child(
                 div()
                     .w_full()
@@ -128,6 +140,36 @@ impl Render for UI {
     }
 }
 
+fn render_error(error: String, cx: &mut ViewContext<UI>) -> impl IntoElement {
+    div()
+        .flex()
+        .flex_row()
+        .items_center()
+        .gap_2()
+        .mx_10()
+        .mt_2()
+        .px_3()
+        .py_1()
+        .rounded(px(10.0))
+        .bg(Color::Error)
+        .text_color(Color::Foreground)
+        .child(div().flex_1().child(error))
+        .child(
+            div()
+                .on_mouse_down(
+                    MouseButton::Left,
+                    cx.listener(|_, _, _| Global::dismiss_error()),
+                )
+                .child(
+                    svg()
+                        .path("x.svg")
+                        .min_w_6()
+                        .min_h_6()
+                        .text_color(Color::Foreground),
+                ),
+        )
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum Interactivity {
     Disabled,
@@ -207,6 +249,7 @@ fn optional_stroke(
 
 fn create_list_state(global_checker: Model<GlobalChecker>) -> ListState {
     let (items, selected) = Global::state();
+    let warnings = lint(&items, Global::chain_depth());
 
     ListState::new(
         items.len(),
@@ -253,6 +296,88 @@ fn create_list_state(global_checker: Model<GlobalChecker>) -> ListState {
                         })
                     },
                 ))
+                .when(items[idx].get(Side::Input).is_some(), |row| {
+                    let global_checker = global_checker.clone();
+                    let matching = items[idx].matching();
+                    row.child(
+                        div()
+                            .min_w_16()
+                            .text_sm()
+                            .when(interactivity.normal(), |div| {
+                                div.hover(|div| div.bg(Color::BackgroundHover))
+                            })
+                            .on_mouse_down(MouseButton::Left, move |_, cx| {
+                                cx.update_model(&global_checker, |_, cx| {
+                                    cx.emit(GlobalToggleMatching(idx))
+                                })
+                            })
+                            .child(matching.to_string()),
+                    )
+                })
+                .when(items[idx].prefix().is_empty().not(), |row| {
+                    let prefix = items[idx]
+                        .prefix()
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>();
+                    row.child(
+                        div()
+                            .min_w_24()
+                            .text_sm()
+                            .child(format!("after {}", prefix.join(", "))),
+                    )
+                })
+                .when_some(items[idx].combo().cloned(), |row, combo| {
+                    row.child(div().min_w_24().text_sm().child(combo.to_string()))
+                })
+                .when_some(items[idx].hold().cloned(), |row, hold| {
+                    row.child(div().min_w_24().text_sm().child(hold.to_string()))
+                })
+                .when_some(items[idx].sticky().cloned(), |row, sticky| {
+                    row.child(div().min_w_24().text_sm().child(sticky.to_string()))
+                })
+                .when_some(items[idx].text().map(str::to_owned), |row, text| {
+                    row.child(div().min_w_24().text_sm().child(format!("{:?}", text)))
+                })
+                .when(items[idx].steps().is_empty().not(), |row| {
+                    let steps = items[idx]
+                        .steps()
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>();
+                    row.child(div().min_w_24().text_sm().child(steps.join(", ")))
+                })
+                .when_some(items[idx].layer().cloned(), |row, layer| {
+                    row.child(div().min_w_24().text_sm().child(layer.to_string()))
+                })
+                .when(items[idx].trigger() != Trigger::Press, |row| {
+                    let trigger = items[idx].trigger().to_string();
+                    row.child(div().min_w_24().text_sm().child(trigger))
+                })
+                .when(items[idx].chain(), |row| {
+                    row.child(div().min_w_24().text_sm().child("chained"))
+                })
+                .when(items[idx].keep_modifiers(), |row| {
+                    row.child(div().min_w_24().text_sm().child("keep modifiers"))
+                })
+                .when_some(items[idx].scope().cloned(), |row, scope| {
+                    row.child(div().min_w_24().text_sm().child(scope.to_string()))
+                })
+                .children(
+                    warnings
+                        .iter()
+                        .filter(|(index, _)| *index == idx)
+                        .map(|(_, warning)| {
+                            div()
+                                .min_w_24()
+                                .px_2()
+                                .text_sm()
+                                .rounded(px(10.0))
+                                .bg(Color::Error)
+                                .text_color(Color::Foreground)
+                                .child(warning.message(&items))
+                        }),
+                )
                 .child(
                     div()
                         .flex()
@@ -1,11 +1,135 @@
-use windows::Win32::UI::Input::KeyboardAndMouse::VIRTUAL_KEY;
+use std::fmt;
 
-pub trait VirtualKeyExtension {
-    fn name(self) -> &'static str;
+/// Windows virtual-key code, used as the platform independent key representation.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, serde::Serialize, serde::Deserialize,
+)]
+#[serde(try_from = "String", into = "String")]
+pub struct VirtualKey(pub u16);
+
+pub const VK_LBUTTON: VirtualKey = VirtualKey(0x01);
+pub const VK_RBUTTON: VirtualKey = VirtualKey(0x02);
+pub const VK_MBUTTON: VirtualKey = VirtualKey(0x04);
+pub const VK_XBUTTON1: VirtualKey = VirtualKey(0x05);
+pub const VK_XBUTTON2: VirtualKey = VirtualKey(0x06);
+pub const VK_SHIFT: VirtualKey = VirtualKey(0x10);
+pub const VK_CONTROL: VirtualKey = VirtualKey(0x11);
+pub const VK_MENU: VirtualKey = VirtualKey(0x12);
+pub const VK_LSHIFT: VirtualKey = VirtualKey(0xA0);
+pub const VK_RSHIFT: VirtualKey = VirtualKey(0xA1);
+pub const VK_LCONTROL: VirtualKey = VirtualKey(0xA2);
+pub const VK_RCONTROL: VirtualKey = VirtualKey(0xA3);
+pub const VK_LMENU: VirtualKey = VirtualKey(0xA4);
+pub const VK_RMENU: VirtualKey = VirtualKey(0xA5);
+pub const VK_LWIN: VirtualKey = VirtualKey(0x5B);
+pub const VK_RWIN: VirtualKey = VirtualKey(0x5C);
+/// Unassigned key, tapped to keep a lone Alt or Win from opening the menu bar or start menu.
+pub const VK_MASK: VirtualKey = VirtualKey(0xE8);
+
+/// Alternative names accepted when parsing keys.
+const ALIASES: &[(&str, VirtualKey)] = &[
+    ("Ctrl", VK_CONTROL),
+    ("LCtrl", VK_LCONTROL),
+    ("RCtrl", VK_RCONTROL),
+    ("Alt", VK_MENU),
+    ("LAlt", VK_LMENU),
+    ("RAlt", VK_RMENU),
+    ("Win", VK_LWIN),
+    ("Enter", VirtualKey(0x0D)),
+    ("Esc", VirtualKey(0x1B)),
+    ("Backspace", VirtualKey(0x08)),
+    ("CapsLock", VirtualKey(0x14)),
+    ("PageUp", VirtualKey(0x21)),
+    ("PageDown", VirtualKey(0x22)),
+];
+
+impl TryFrom<String> for VirtualKey {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        Self::from_name(name.trim()).ok_or_else(|| format!("Unknown key '{}'", name))
+    }
 }
 
-impl VirtualKeyExtension for VIRTUAL_KEY {
-    fn name(self) -> &'static str {
+impl From<VirtualKey> for String {
+    fn from(key: VirtualKey) -> Self {
+        key.to_string()
+    }
+}
+
+/// The name, or the code like `0xE1` for keys without a name.
+impl fmt::Display for VirtualKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.is_known() {
+            true => write!(f, "{}", self.name()),
+            false => write!(f, "0x{:02X}", self.0),
+        }
+    }
+}
+
+impl VirtualKey {
+    pub fn is_known(self) -> bool {
+        self.name() != "..."
+    }
+
+    pub fn is_modifier(self) -> bool {
+        matches!(
+            self,
+            VK_SHIFT
+                | VK_CONTROL
+                | VK_MENU
+                | VK_LSHIFT
+                | VK_RSHIFT
+                | VK_LCONTROL
+                | VK_RCONTROL
+                | VK_LMENU
+                | VK_RMENU
+                | VK_LWIN
+                | VK_RWIN
+        )
+    }
+
+    /// Modifier independent of the side, other keys are unchanged.
+    pub fn generic(self) -> Self {
+        match self {
+            VK_LSHIFT | VK_RSHIFT => VK_SHIFT,
+            VK_LCONTROL | VK_RCONTROL => VK_CONTROL,
+            VK_LMENU | VK_RMENU => VK_MENU,
+            _ => self,
+        }
+    }
+
+    /// Left key of a generic modifier, which can be sent as a single key.
+    pub fn left(self) -> Self {
+        match self {
+            VK_SHIFT => VK_LSHIFT,
+            VK_CONTROL => VK_LCONTROL,
+            VK_MENU => VK_LMENU,
+            _ => self,
+        }
+    }
+
+    /// Reverse of [`VirtualKey::name`], case insensitive and with some common aliases.
+    /// Codes like `0xE1` are accepted for keys without a name.
+    pub fn from_name(name: &str) -> Option<Self> {
+        if let Some(code) = name.strip_prefix("0x").or_else(|| name.strip_prefix("0X")) {
+            return u8::from_str_radix(code, 16)
+                .ok()
+                .map(|code| VirtualKey(code.into()));
+        }
+        if let Some((_, key)) = ALIASES
+            .iter()
+            .find(|(alias, _)| alias.eq_ignore_ascii_case(name))
+        {
+            return Some(*key);
+        }
+        (0..=0xFF)
+            .map(VirtualKey)
+            .filter(|key| key.is_known())
+            .find(|key| key.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn name(self) -> &'static str {
         match self.0 {
             0x01 => "LButton",
             0x02 => "RButton",
@@ -181,3 +305,22 @@ impl VirtualKeyExtension for VIRTUAL_KEY {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_round_trip() {
+        for key in (0..=0xFF).map(VirtualKey) {
+            assert_eq!(VirtualKey::try_from(String::from(key)), Ok(key));
+        }
+    }
+
+    #[test]
+    fn unknown_keys_use_the_code() {
+        assert_eq!(String::from(VirtualKey(0xE1)), "0xE1");
+        assert_eq!(VirtualKey::from_name("0xe1"), Some(VirtualKey(0xE1)));
+        assert_eq!(VirtualKey::from_name("0x100"), None);
+    }
+}